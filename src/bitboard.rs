@@ -0,0 +1,109 @@
+//! A set of intersections stored as a bitmap, one bit per point of the
+//! biggest possible board.
+
+use board::board_maxsize;
+
+static words : uint = (board_maxsize * board_maxsize + 63) / 64;
+
+/// A set of board points, coordinates being 1-based as in Board.
+pub struct Bitboard {
+    bits: [u64, ..words]
+}
+
+fn bit_index(x: uint, y: uint) -> (uint, u64) {
+    let i = (x-1) * board_maxsize + (y-1);
+    (i / 64, 1u64 << (i % 64))
+}
+
+impl Bitboard {
+    pub fn new() -> Bitboard {
+        Bitboard { bits: [0u64, ..words] }
+    }
+
+    pub fn insert(&mut self, x: uint, y: uint) {
+        let (w, mask) = bit_index(x, y);
+        self.bits[w] |= mask;
+    }
+
+    pub fn remove(&mut self, x: uint, y: uint) {
+        let (w, mask) = bit_index(x, y);
+        self.bits[w] &= !mask;
+    }
+
+    pub fn contains(&self, x: uint, y: uint) -> bool {
+        let (w, mask) = bit_index(x, y);
+        self.bits[w] & mask != 0
+    }
+
+    /// Number of points in the set.
+    pub fn len(&self) -> uint {
+        self.bits.iter().fold(0u, |acc, w| acc + w.count_ones() as uint)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|w| *w == 0)
+    }
+
+    /// Adds all points of other to this set.
+    pub fn union_with(&mut self, other: &Bitboard) {
+        for i in range(0, words) {
+            self.bits[i] |= other.bits[i];
+        }
+    }
+
+    /// Removes all points of other from this set.
+    pub fn difference_with(&mut self, other: &Bitboard) {
+        for i in range(0, words) {
+            self.bits[i] &= !other.bits[i];
+        }
+    }
+
+    /// Number of points present in both sets.
+    pub fn intersection_len(&self, other: &Bitboard) -> uint {
+        let mut count = 0u;
+        for i in range(0, words) {
+            count += (self.bits[i] & other.bits[i]).count_ones() as uint;
+        }
+        count
+    }
+
+    pub fn iter<'a>(&'a self) -> Points<'a> {
+        Points { board: self, word: 0, remaining: self.bits[0] }
+    }
+}
+
+impl Clone for Bitboard {
+    fn clone(&self) -> Bitboard {
+        *self
+    }
+}
+
+impl PartialEq for Bitboard {
+    fn eq(&self, other: &Bitboard) -> bool {
+        range(0, words).all(|i| self.bits[i] == other.bits[i])
+    }
+}
+
+/// Iterator over the points of a Bitboard, yielding (x, y) coordinates.
+pub struct Points<'a> {
+    board: &'a Bitboard,
+    word: uint,
+    remaining: u64
+}
+
+impl<'a> Iterator<(uint, uint)> for Points<'a> {
+    fn next(&mut self) -> Option<(uint, uint)> {
+        while self.remaining == 0 {
+            self.word += 1;
+            if self.word >= words {
+                return None;
+            }
+            self.remaining = self.board.bits[self.word];
+        }
+        let bit = self.remaining.trailing_zeros() as uint;
+        // clear the lowest set bit
+        self.remaining &= self.remaining - 1;
+        let i = self.word * 64 + bit;
+        Some((i / board_maxsize + 1, i % board_maxsize + 1))
+    }
+}
@@ -1,5 +1,13 @@
+use std::cmp::min;
 use std::collections::{DList, TreeSet, SmallIntMap, Deque};
-use std::collections::treemap::SetItems;
+use std::fmt;
+use std::hash;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use bitboard::{Bitboard, Points};
+use coords;
+use smallvec::SmallVec;
 
 macro_rules! single_match(
     ($mtch:expr : $ptrn:pat => $blk:block) => (
@@ -10,7 +18,11 @@ macro_rules! single_match(
     )
 )
 
-static board_maxsize : uint = 25;
+pub static board_maxsize : uint = 25;
+
+/// Groups with a strength below this one are in danger, see
+/// Board::group_strength.
+pub static weak_group_strength : uint = 4;
 
 #[deriving(PartialEq)]
 pub enum Colour {
@@ -18,8 +30,19 @@ pub enum Colour {
     White
 }
 
+impl fmt::Show for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Black => write!(f, "B"),
+            White => write!(f, "W")
+        }
+    }
+}
+
 // structs needed for board representation
 
+/// Content of a point. The id of a stone is internal to the board, it
+/// does not always match the key of its group in Board::get_groups.
 #[deriving(PartialEq)]
 pub enum Intersection {
     Stone(Colour, uint),
@@ -28,15 +51,15 @@ pub enum Intersection {
 
 #[deriving(Clone)]
 pub struct Group {
-    stones: TreeSet<(uint, uint)>,
-    liberties: TreeSet<(uint, uint)>
+    stones: Bitboard,
+    liberties: Bitboard
 }
 
 impl Group {
     pub fn new() -> Group {
         Group {
-            stones: TreeSet::new(),
-            liberties: TreeSet::new()
+            stones: Bitboard::new(),
+            liberties: Bitboard::new()
         }
     }
 
@@ -44,32 +67,38 @@ impl Group {
         self.liberties.is_empty()
     }
 
+    pub fn is_in_atari(&self) -> bool {
+        self.liberties.len() == 1
+    }
+
     pub fn add_stone(&mut self, x:uint, y: uint) {
-        self.stones.insert((x,y));
-        self.liberties.remove(&(x,y));
+        self.stones.insert(x, y);
+        self.liberties.remove(x, y);
     }
 
     pub fn add_liberty(&mut self, x: uint,y:uint) {
-        self.liberties.insert((x,y));
+        self.liberties.insert(x, y);
     }
 
     pub fn remove_liberty(&mut self, x: uint, y: uint) {
-        self.liberties.remove(&(x,y));
+        self.liberties.remove(x, y);
+    }
+
+    pub fn has_liberty(&self, x: uint, y: uint) -> bool {
+        self.liberties.contains(x, y)
     }
 
     pub fn absorb(&mut self, other: Group) {
-        self.stones.extend(other.stones.move_iter());
-        self.liberties.extend(other.liberties.move_iter());
-        for &(x,y) in self.liberties.intersection(&self.stones) {
-            self.liberties.remove(&(x,y));
-        }
+        self.stones.union_with(&other.stones);
+        self.liberties.union_with(&other.liberties);
+        self.liberties.difference_with(&self.stones);
     }
 
     pub fn stone_count(&self) -> uint {
         self.stones.len()
     }
 
-    pub fn get_stones<'a>(&'a self) -> SetItems<'a, (uint, uint)> {
+    pub fn get_stones<'a>(&'a self) -> Points<'a> {
         self.stones.iter()
     }
 
@@ -77,42 +106,442 @@ impl Group {
         self.liberties.len()
     }
 
-    pub fn get_liberties<'a>(&'a self) -> SetItems<'a, (uint, uint)> {
+    pub fn get_liberties<'a>(&'a self) -> Points<'a> {
         self.liberties.iter()
     }
 
-    pub fn dismantle(self) -> TreeSet<(uint, uint)> {
+    /// Number of liberties this group shares with another one.
+    pub fn shared_liberty_count(&self, other: &Group) -> uint {
+        self.liberties.intersection_len(&other.liberties)
+    }
+
+    pub fn dismantle(self) -> Bitboard {
         self.stones
     }
 }
 
+impl fmt::Show for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stones: Vec<String> = self.stones.iter().map(|(x, y)| coords::format_vertex(x, y)).collect();
+        let liberties: Vec<String> = self.liberties.iter().map(|(x, y)| coords::format_vertex(x, y)).collect();
+        write!(f, "[{}] liberties: [{}]", stones.connect(" "), liberties.connect(" "))
+    }
+}
+
 // structs needed for history
 
+#[deriving(PartialEq)]
 pub enum Vertex {
     Put(uint, uint),
     Pass
 }
 
+impl fmt::Show for Vertex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Put(x, y) => write!(f, "{}", coords::format_vertex(x, y)),
+            Pass => write!(f, "pass")
+        }
+    }
+}
+
 pub struct Move {
     pub player: Colour,
     pub move: Vertex,
-    pub removed: Vec<Group>
+    /// Groups taken off the board, dropped once the move is compacted, see
+    /// Board::set_history_limit. Kept on the heap, unlike the small vectors
+    /// of the move path, as history entries live for the whole game.
+    pub removed: Vec<Group>,
+    /// Whether this move was a suicide, 'removed' then holds the group of
+    /// the player instead of captured groups.
+    pub suicide: bool,
+    /// Ko point created by this move, if any.
+    pub ko: Option<(uint, uint)>,
+    /// Ko point before this move, restored when it is undone.
+    pub previous_ko: Option<(uint, uint)>,
+    /// Thinking time in seconds, when known.
+    pub time_spent: Option<f64>,
+    /// CPU time used to think, in seconds, when known.
+    pub cpu_time_spent: Option<f64>,
+    /// Hash of the position after this move.
+    pub hash: u64
+}
+
+impl Move {
+    /// Opponent stones captured by this move, a suicide captures none.
+    pub fn captured(&self) -> Vec<(uint, uint)> {
+        if self.suicide {
+            Vec::new()
+        } else {
+            self.removed.iter().flat_map(|grp| grp.get_stones()).collect()
+        }
+    }
+
+    /// Number of opponent stones captured by this move.
+    pub fn capture_count(&self) -> uint {
+        if self.suicide {
+            0
+        } else {
+            self.removed.iter().fold(0, |n, grp| n + grp.stone_count())
+        }
+    }
+}
+
+impl fmt::Show for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.player, self.move)
+    }
+}
+
+/// Summary of a move of the history, see Board::history_entries.
+pub struct MoveInfo {
+    /// Move number, starting at 1.
+    pub number: uint,
+    pub player: Colour,
+    pub move: Vertex,
+    /// Opponent stones captured by this move.
+    pub captured: Vec<(uint, uint)>,
+    pub created_ko: bool,
+    pub time_spent: Option<f64>,
+    pub cpu_time_spent: Option<f64>
+}
+
+/// The moves of a game from its starting position, which can be replayed
+/// on a board.
+pub struct GameRecord {
+    pub size: uint,
+    pub komi: f32,
+    /// Stones of the starting position, such as a handicap.
+    pub setup: Vec<(Colour, uint, uint)>,
+    /// Player to move first, None to take the player of the first move,
+    /// or White after setup stones without moves.
+    pub start_player: Option<Colour>,
+    pub moves: Vec<(Colour, Vertex)>
+}
+
+impl GameRecord {
+    /// An empty game on a board of the given size.
+    pub fn new(size: uint, komi: f32) -> GameRecord {
+        GameRecord { size: size, komi: komi, setup: Vec::new(), start_player: None, moves: Vec::new() }
+    }
+
+    /// Replays the game on a new board.
+    pub fn replay(&self) -> Result<Board, String> {
+        let mut goban = Board::new();
+        try!(self.replay_on(&mut goban));
+        Ok(goban)
+    }
+
+    /// Replays the game on the given board, which is cleared first but
+    /// keeps its rules. Stops at the first illegal move, the board is
+    /// then left in the starting position.
+    pub fn replay_on(&self, goban: &mut Board) -> Result<(), String> {
+        if !goban.resize(self.size) {
+            return Err(format!("invalid board size {}", self.size));
+        }
+        goban.set_komi(self.komi);
+        for &(colour, x, y) in self.setup.iter() {
+            if !goban.place_setup_stone(colour, x, y) {
+                return Err(format!("invalid setup stone {} {}", colour, Put(x, y)));
+            }
+        }
+        match self.start_player {
+            Some(colour) => goban.start_player = colour,
+            None if !self.setup.is_empty() => {
+                // after a handicap White usually moves first, but the record knows best
+                goban.start_player = match self.moves.as_slice().head() {
+                    Some(&(colour, _)) => colour,
+                    None => White
+                };
+            }
+            None => {}
+        }
+        match goban.play_sequence(self.moves.as_slice()) {
+            Ok(()) => Ok(()),
+            Err((i, reason)) => {
+                let (colour, move) = self.moves[i];
+                Err(format!("move {} ({} {}) is illegal: {}", i + 1, colour, move, reason))
+            }
+        }
+    }
+}
+
+/// Outcome of a finished game.
+#[deriving(PartialEq)]
+pub enum GameResult {
+    /// The given colour won because its opponent resigned.
+    WinByResignation(Colour),
+    /// The given colour won by the given number of points.
+    WinByPoints(Colour, f32),
+    /// Both colours have the same score.
+    Jigo
+}
+
+impl GameResult {
+    /// Result of a game won by points, from a score positive when Black
+    /// is ahead.
+    pub fn from_score(score: f32) -> GameResult {
+        if score > 0.0 {
+            WinByPoints(Black, score)
+        } else if score < 0.0 {
+            WinByPoints(White, -score)
+        } else {
+            Jigo
+        }
+    }
+}
+
+impl fmt::Show for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WinByResignation(winner) => write!(f, "{}+R", winner),
+            WinByPoints(winner, points) => write!(f, "{}+{}", winner, points),
+            Jigo => write!(f, "0")
+        }
+    }
+}
+
+/// Area statistics of a position, see Board::counts.
+pub struct Counts {
+    pub empty: uint,
+    pub black_stones: uint,
+    pub white_stones: uint,
+    /// Empty points in regions bordered by black stones only.
+    pub black_territory: uint,
+    /// Empty points in regions bordered by white stones only.
+    pub white_territory: uint
+}
+
+/// Reason why a move is illegal, see Board::play.
+#[deriving(PartialEq)]
+pub enum IllegalMove {
+    /// The point is not on the board.
+    OutOfBounds,
+    /// There is already a stone on the point.
+    Occupied,
+    /// The move retakes a ko, or repeats a position under a superko rule.
+    Ko,
+    /// The stone would have no liberty, and suicide is not allowed.
+    Suicide,
+    /// A player resigned, no move follows.
+    GameOver
+}
+
+impl fmt::Show for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutOfBounds => write!(f, "point outside of the board"),
+            Occupied => write!(f, "point already occupied"),
+            Ko => write!(f, "ko"),
+            Suicide => write!(f, "suicide"),
+            GameOver => write!(f, "game is over")
+        }
+    }
+}
+
+/// Rule used to forbid repeating positions.
+#[deriving(PartialEq, Show)]
+pub enum KoRule {
+    /// Only the immediate recapture of a single stone ko is forbidden.
+    SimpleKo,
+    /// No move may recreate a position of the game, whoever is to play.
+    PositionalSuperko,
+    /// No move may recreate a position of the game with the same player
+    /// to play.
+    SituationalSuperko
+}
+
+/// Classification of an empty point as an eye of a colour.
+#[deriving(PartialEq, Show)]
+pub enum EyeKind {
+    /// The point is not surrounded by stones of the colour.
+    NotEye,
+    /// The point is surrounded but the opponent controls enough diagonals
+    /// to eventually break the connection around it.
+    FalseEye,
+    /// The point is a single-point eye.
+    TrueEye
+}
+
+/// The 8 symmetries of the board, rotations being clockwise.
+#[deriving(PartialEq, Show)]
+pub enum Symmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Left and right are swapped.
+    MirrorX,
+    /// Top and bottom are swapped.
+    MirrorY,
+    /// Reflection across the diagonal going through (1, 1).
+    Transpose,
+    /// Reflection across the other diagonal.
+    AntiTranspose
+}
+
+pub static symmetries : [Symmetry, ..8] = [Identity, Rotate90, Rotate180, Rotate270,
+                                           MirrorX, MirrorY, Transpose, AntiTranspose];
+
+impl Symmetry {
+    /// Image of a point of a board of the given size.
+    pub fn apply(&self, size: uint, x: uint, y: uint) -> (uint, uint) {
+        let n = size + 1;
+        match *self {
+            Identity => (x, y),
+            Rotate90 => (y, n - x),
+            Rotate180 => (n - x, n - y),
+            Rotate270 => (n - y, x),
+            MirrorX => (n - x, y),
+            MirrorY => (x, n - y),
+            Transpose => (y, x),
+            AntiTranspose => (n - y, n - x)
+        }
+    }
+
+    /// Image of a move on a board of the given size.
+    pub fn apply_move(&self, size: uint, move: Vertex) -> Vertex {
+        match move {
+            Put(x, y) => {
+                let (a, b) = self.apply(size, x, y);
+                Put(a, b)
+            }
+            Pass => Pass
+        }
+    }
+
+    /// The symmetry undoing this one.
+    pub fn inverse(&self) -> Symmetry {
+        match *self {
+            Rotate90 => Rotate270,
+            Rotate270 => Rotate90,
+            other => other
+        }
+    }
+}
+
+// pseudo-random key of a stone for the position hash, derived from its
+// colour and point with the splitmix64 finalizer so no table is needed
+fn zobrist_key(colour: Colour, x: uint, y: uint) -> u64 {
+    let c = match colour { Black => 0u64, White => 1u64 };
+    let mut z = ((((x * board_maxsize + y) as u64) << 1 | c) + 1) * 0x9E3779B97F4A7C15u64;
+    z = (z ^ (z >> 30)) * 0xBF58476D1CE4E5B9u64;
+    z = (z ^ (z >> 27)) * 0x94D049BB133111EBu64;
+    z ^ (z >> 31)
+}
+
+// a connected region of empty points and the colours bordering it
+struct EmptyRegion {
+    points: Vec<(uint, uint)>,
+    borders_black: bool,
+    borders_white: bool
+}
+
+/// Receives the changes of a board, see Board::add_observer. All the
+/// methods do nothing by default.
+pub trait BoardObserver {
+    /// A move, stone or pass, was played.
+    fn on_play(&mut self, _player: Colour, _move: Vertex) {}
+    /// Stones of the given colour were captured by the last move played.
+    fn on_capture(&mut self, _colour: Colour, _stones: &[(uint, uint)]) {}
+    /// The given move was undone.
+    fn on_undo(&mut self, _player: Colour, _move: Vertex) {}
+}
+
+/// Stones differing between two positions, see Board::diff.
+pub struct BoardDiff {
+    /// Stones present in the second position only.
+    pub added: Vec<(Colour, uint, uint)>,
+    /// Stones present in the first position only.
+    pub removed: Vec<(Colour, uint, uint)>
+}
+
+impl BoardDiff {
+    /// Whether both positions have the same stones.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A point of the game to come back to, see Board::checkpoint.
+pub struct Checkpoint {
+    moves: uint,
+    hash: u64
+}
+
+/// Iterator over the points of a board, see Board::iter.
+pub struct Intersections<'a> {
+    board: &'a Board,
+    next: uint
+}
+
+impl<'a> Iterator<(uint, uint, Option<Colour>)> for Intersections<'a> {
+    fn next(&mut self) -> Option<(uint, uint, Option<Colour>)> {
+        let size = self.board.size;
+        if self.next >= size * size {
+            return None;
+        }
+        let (x, y) = (self.next / size + 1, self.next % size + 1);
+        self.next += 1;
+        Some((x, y, match self.board.point(x, y) {
+            Stone(col, _) => Some(col),
+            Empty => None
+        }))
+    }
 }
 
 // board itself
 
+/// Owner of every point of a board, indexed as [x-1][y-1] with room for
+/// the largest board, None for neutral points.
+pub type OwnershipMap = [[Option<Colour>, ..board_maxsize], ..board_maxsize];
+
+/// A score with the owner of every point, see Board::area_scoring and
+/// Board::territory_scoring.
+pub struct Scoring {
+    /// Komi included, positive when Black is ahead.
+    pub score: f32,
+    /// Colour each point counted for, None for neutral points and points
+    /// counted for nobody.
+    pub ownership: OwnershipMap
+}
+
+/// An immutable copy of a position which can be shared between tasks,
+/// for example by a search running in parallel with the GTP loop.
+pub type Snapshot = Arc<Board>;
+
 /// This struct represents a board. It stores information about
 /// groups to automatically remove dead stones, allow undoing
-/// and detect simple kos.
+/// and detect kos.
 #[allow(dead_code)]
 pub struct Board {
-    stones: [[Intersection, ..board_maxsize], ..board_maxsize],
+    // points column by column, see Board::point
+    stones: Vec<Intersection>,
     history: DList<Move>,
     groups: SmallIntMap<Group>,
+    // union-find of the stone ids: every absorbed group id leads to the
+    // id of the group which absorbed it, stones are never repainted
+    links: SmallIntMap<uint>,
     size: uint,
     white_dead: uint,
     black_dead: uint,
-    current_ko: (uint, uint)
+    current_ko: (uint, uint),
+    komi: f32,
+    ko_rule: KoRule,
+    allow_suicide: bool,
+    // xor of the zobrist keys of all the stones on the board
+    hash: u64,
+    // hash of the position before the first move of the history
+    start_hash: u64,
+    // player to move in that position
+    start_player: Colour,
+    // player who resigned, ending the game
+    resigned: Option<Colour>,
+    // number of moves at the front of the history whose removed groups
+    // were dropped, they cannot be undone
+    compacted: uint,
+    history_limit: Option<uint>,
+    observers: Vec<Box<BoardObserver + Send + Sync>>
 }
 
 impl Board {
@@ -120,19 +549,72 @@ impl Board {
     /// Creates a new Board.
     pub fn new() -> Board {
         Board {
-            stones: [[Empty, ..board_maxsize], ..board_maxsize],
+            stones: Vec::from_elem(19 * 19, Empty),
             history: DList::new(),
             groups: SmallIntMap::new(),
+            links: SmallIntMap::new(),
             size: 19,
             white_dead: 0,
             black_dead: 0,
-            current_ko: (0, 0)
+            current_ko: (0, 0),
+            komi: 0.0,
+            ko_rule: SimpleKo,
+            allow_suicide: false,
+            hash: 0,
+            start_hash: 0,
+            start_player: Black,
+            resigned: None,
+            compacted: 0,
+            history_limit: None,
+            observers: Vec::new()
+        }
+    }
+
+    /// Iterates over the points of the board as (x, y, stone), column by
+    /// column, stone being None for an empty point.
+    pub fn iter<'a>(&'a self) -> Intersections<'a> {
+        Intersections { board: self, next: 0 }
+    }
+
+    /// Colour of the stone at the given point, None if it is empty. The
+    /// coordinates start at 1 and must be on the board.
+    pub fn at(&self, x: uint, y: uint) -> Option<Colour> {
+        match self.point(x, y) {
+            Stone(col, _) => Some(col),
+            Empty => None
+        }
+    }
+
+    /// Key in get_groups of the group of the stone at the given point, None
+    /// if it is empty.
+    pub fn group_at(&self, x: uint, y: uint) -> Option<uint> {
+        match self.point(x, y) {
+            Stone(_, id) => Some(self.find(id)),
+            Empty => None
         }
     }
 
-    /// Allows read-only access to the board
-    pub fn get_board<'a>(&'a self) -> &'a [[Intersection, ..board_maxsize], ..board_maxsize] {
-        &self.stones
+    /// Stones to add and remove to go from this position to the other one.
+    /// A stone which changed colour is both removed and added.
+    pub fn diff(&self, other: &Board) -> BoardDiff {
+        let mut diff = BoardDiff { added: Vec::new(), removed: Vec::new() };
+        let size = if self.size > other.size { self.size } else { other.size };
+        for x in range(1u, size+1) {
+            for y in range(1u, size+1) {
+                // points beyond the size of a board are empty
+                let content = |goban: &Board| {
+                    if x <= goban.size && y <= goban.size { goban.point(x, y) } else { Empty }
+                };
+                match (content(self), content(other)) {
+                    (Stone(before, _), Stone(after, _)) if before == after => {}
+                    (before, after) => {
+                        single_match!(before : Stone(col, _) => { diff.removed.push((col, x, y)); });
+                        single_match!(after : Stone(col, _) => { diff.added.push((col, x, y)); });
+                    }
+                }
+            }
+        }
+        diff
     }
 
     /// Allows read-only access to the history
@@ -140,6 +622,71 @@ impl Board {
         &self.history
     }
 
+    /// Last move of the history with its player, None at the start of
+    /// the game.
+    pub fn last_move(&self) -> Option<(Colour, Vertex)> {
+        self.history.back().map(|mv| (mv.player, mv.move))
+    }
+
+    /// Whether the last two moves of the history are passes.
+    pub fn both_passed(&self) -> bool {
+        self.history.iter().rev().take(2).filter(|mv| mv.move == Pass).count() == 2
+    }
+
+    /// Number of moves played since the starting position, passes
+    /// included.
+    pub fn move_number(&self) -> uint {
+        self.history.len()
+    }
+
+    /// Number of the move which put the stone at the given point, None if
+    /// the point is empty or the stone is part of the starting position.
+    pub fn move_number_at(&self, x: uint, y: uint) -> Option<uint> {
+        if self.point(x, y) == Empty {
+            return None;
+        }
+        // a captured stone can only be replaced by a later move
+        let len = self.history.len();
+        self.history.iter().rev().position(|mv| mv.move == Put(x, y)).map(|i| len - i)
+    }
+
+    /// Move numbers of all the stones played since the starting position,
+    /// as (x, y, number), computed in a single pass over the history.
+    pub fn stone_numbers(&self) -> Vec<(uint, uint, uint)> {
+        let size = self.size;
+        let mut numbers = Vec::from_elem(size * size, 0u);
+        for (i, mv) in self.history.iter().enumerate() {
+            single_match!(mv.move : Put(x, y) => {
+                *numbers.get_mut((x-1) * size + y-1) = i + 1;
+            });
+        }
+        self.iter().filter(|&(x, y, stone)| stone.is_some() && numbers[(x-1) * size + y-1] > 0)
+                   .map(|(x, y, _)| (x, y, numbers[(x-1) * size + y-1]))
+                   .collect()
+    }
+
+    /// Summaries of all the moves of the history, oldest first.
+    pub fn history_entries(&self) -> Vec<MoveInfo> {
+        self.history.iter().enumerate().map(|(i, mv)| MoveInfo {
+            number: i + 1,
+            player: mv.player,
+            move: mv.move,
+            captured: mv.captured(),
+            created_ko: mv.ko.is_some(),
+            time_spent: mv.time_spent,
+            cpu_time_spent: mv.cpu_time_spent
+        }).collect()
+    }
+
+    /// Records the thinking time of the last move of the history, both
+    /// elapsed and CPU time.
+    pub fn set_last_move_time(&mut self, seconds: f64, cpu_seconds: f64) {
+        single_match!(self.history.back_mut() : Some(mv) => {
+            mv.time_spent = Some(seconds);
+            mv.cpu_time_spent = Some(cpu_seconds);
+        });
+    }
+
     /// Allow read-only access to the groups data
     pub fn get_groups<'a>(&'a self) -> &'a SmallIntMap<Group> {
         &self.groups
@@ -155,6 +702,75 @@ impl Board {
         (self.black_dead, self.white_dead)
     }
 
+    /// Komi given to White, used when scoring the game.
+    pub fn get_komi(&self) -> f32 {
+        self.komi
+    }
+
+    /// Sets the komi, it is kept when the board is cleared or resized.
+    pub fn set_komi(&mut self, komi: f32) {
+        self.komi = komi;
+    }
+
+    /// Zobrist hash of the stones on the board, updated incrementally
+    /// by every move. The player to move is not part of it.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Smallest hash of the 8 symmetric images of the position, the same
+    /// for all of them. Like hash, the player to move is not part of it.
+    pub fn canonical_hash(&self) -> u64 {
+        self.canonical().val0()
+    }
+
+    /// Symmetry turning the position into the image whose hash is the
+    /// canonical hash. Moves of an opening book stored for that image are
+    /// mapped back with its inverse.
+    pub fn canonical_symmetry(&self) -> Symmetry {
+        self.canonical().val1()
+    }
+
+    fn canonical(&self) -> (u64, Symmetry) {
+        let mut best = (self.hash, Identity);
+        for &symmetry in symmetries.iter().skip(1) {
+            let mut hash = 0u64;
+            for (x, y, stone) in self.iter() {
+                single_match!(stone : Some(colour) => {
+                    let (a, b) = symmetry.apply(self.size, x, y);
+                    hash ^= zobrist_key(colour, a, b);
+                });
+            }
+            if hash < best.val0() {
+                best = (hash, symmetry);
+            }
+        }
+        best
+    }
+
+    /// Rule used against repeated positions.
+    pub fn get_ko_rule(&self) -> KoRule {
+        self.ko_rule
+    }
+
+    /// Sets the rule used against repeated positions, it is kept when the
+    /// board is cleared or resized.
+    pub fn set_ko_rule(&mut self, rule: KoRule) {
+        self.ko_rule = rule;
+    }
+
+    /// Whether multi-stone suicide is legal.
+    pub fn get_allow_suicide(&self) -> bool {
+        self.allow_suicide
+    }
+
+    /// Allows or forbids multi-stone suicide, as allowed by the New Zealand
+    /// and Tromp-Taylor rules. It is kept when the board is cleared or
+    /// resized.
+    pub fn set_allow_suicide(&mut self, allow: bool) {
+        self.allow_suicide = allow;
+    }
+
     /// Option to the coordinates of current ko.
     pub fn get_current_ko(&self) -> Option<(uint, uint)> {
         if self.current_ko == (0, 0) {
@@ -164,170 +780,1224 @@ impl Board {
         }
     }
 
-    /// Resets the board and clear the history. The board is then
-    /// ready for a new game.
-    pub fn clear(&mut self) {
-        self.history.clear();
-        self.groups.clear();
-        self.stones = [[Empty, ..board_maxsize], ..board_maxsize];
+    /// Counts empty points, stones and a territory estimate for both
+    /// colours in a single scan of the board.
+    pub fn counts(&self) -> Counts {
+        let mut counts = Counts { empty: 0, black_stones: 0, white_stones: 0,
+                                  black_territory: 0, white_territory: 0 };
+        for i in range(0, self.size) {
+            for j in range(0, self.size) {
+                match self.point(i + 1, j + 1) {
+                    Stone(Black, _) => counts.black_stones += 1,
+                    Stone(White, _) => counts.white_stones += 1,
+                    Empty => counts.empty += 1
+                }
+            }
+        }
+        for region in self.empty_regions().iter() {
+            match (region.borders_black, region.borders_white) {
+                (true, false) => counts.black_territory += region.points.len(),
+                (false, true) => counts.white_territory += region.points.len(),
+                _ => {}
+            }
+        }
+        counts
     }
 
-    /// Change the size of the board, must be between 1 and 25 inclusive.
-    pub fn resize(&mut self, newsize: uint) -> bool {
-        if newsize > 0 && newsize <= board_maxsize {
-            self.clear();
-            self.size = newsize;
-            true
-        } else {
-            false
-        }
+    /// Tromp-Taylor area score, komi included: stones plus empty points
+    /// reaching only stones of one colour. Positive when Black is ahead.
+    /// All the stones on the board are considered alive.
+    pub fn score(&self) -> f32 {
+        let counts = self.counts();
+        let black = counts.black_stones + counts.black_territory;
+        let white = counts.white_stones + counts.white_territory;
+        black as f32 - white as f32 - self.komi
     }
 
-    /// Returns a copy of the board without history, can thus be used to think,
-    /// experiment and prepare the next move.
-    pub fn clone_without_history(&self) -> Board {
-        Board {
-            stones: {
-                let mut array = [[Empty, ..board_maxsize], ..board_maxsize];
-                for i in range(0, self.size) {
-                    for j in range(0, self.size) {
-                        array[i][j] = self.stones[i][j];
-                    }
-                }
-            array },
-            history: DList::new(),
-            groups: self.groups.clone(),
-            size: self.size,
-            white_dead: self.white_dead,
-            black_dead: self.black_dead,
-            current_ko: self.current_ko
-        }
+    /// Area score with the owner of every point: stones belong to their
+    /// colour, and empty regions to the only colour bordering them.
+    pub fn area_scoring(&self) -> Scoring {
+        Scoring { score: self.score(), ownership: self.territory_map() }
     }
 
-    fn loop_over_neighbours(x:uint, y:uint, size:uint, func: |uint, uint|  -> ()) {
-        if x > 1 { func(x-1, y); }
-        if y > 1 { func(x, y-1); }
-        if x < size { func(x+1, y); }
-        if y < size { func(x, y+1); }
+    /// Result of the game according to the area score.
+    pub fn area_result(&self) -> GameResult {
+        GameResult::from_score(self.score())
     }
 
-    // shall be called only if you KNOW a stone is there
-    fn gid_of_stone(&self, x:uint, y:uint) -> uint {
-        match self.stones[x-1][y-1] {
-            Stone(_, gid) => gid,
-            _ => unreachable!()
-        }
+    /// The player resigns, which ends the game. The next undo takes the
+    /// resignation back.
+    pub fn resign(&mut self, player: Colour) {
+        self.resigned = Some(player);
     }
 
-    // we want to reuse old keys
-    fn next_gid(&self) -> uint {
-        let mut key = 0u;
-        while self.groups.contains_key(&key) { key += 1u; }
-        key
+    /// Player who resigned, if any.
+    pub fn resigned(&self) -> Option<Colour> {
+        self.resigned
     }
 
-    fn split_group(&mut self, gid: uint, unput: (uint,uint)) {
-        self.stones[unput.val0()-1][unput.val1()-1] = Empty;
-        let mut oldstones = self.groups.pop(&gid).unwrap().dismantle();
-        oldstones.remove(&unput);
-        while !oldstones.is_empty() {
-            // retrieve a random item
-            let &(x, y) = oldstones.iter().next().unwrap();
-            let newgid = self.next_gid();
-            self.groups.insert(newgid, Group::new());
-            // targetted references for closures
-            let ref mut newgroup = self.groups.find_mut(&newgid).unwrap();
-            let &mut mystones = &self.stones;
-            let mut to_loop = DList::new();
-            // loop preparation
-            to_loop.push((x,y));
-            oldstones.remove(&(x,y));
-            // loop on the whole groupe
-            while !to_loop.is_empty() {
-                let (v, w) = to_loop.pop().unwrap();
-                mystones[v-1][w-1] = match self.stones[v-1][w-1] {
-                    Stone(col, id) if id == gid => Stone(col, newgid),
-                    _ => unreachable!() // same here
-                };
-                newgroup.add_stone(v, w);
-                Board::loop_over_neighbours(v, w, self.size, |a, b| {
-                    if oldstones.contains(&(a,b)) {
-                        to_loop.push((a,b));
-                        oldstones.remove(&(a,b));
-                    } else if mystones[a-1][b-1] == Empty {
-                        newgroup.add_liberty(a,b);
-                    }
-                });
-            }
+    /// Result of the game: a win by resignation if a player resigned, the
+    /// result according to the area score otherwise.
+    pub fn final_result(&self) -> GameResult {
+        match self.resigned {
+            Some(Black) => WinByResignation(White),
+            Some(White) => WinByResignation(Black),
+            None => self.area_result()
         }
     }
 
-    /// Undo the last move.
-    pub fn undo(&mut self) -> bool {
-        match self.history.pop() {
-            None => false,
-            Some(Move{player: _, move: Pass, removed: _}) => true,
-            Some(Move{player: player, move: Put(x,y), removed: removed}) => {
-                let oldgid = self.gid_of_stone(x, y);
-                self.split_group(oldgid, (x,y));
-                // restore removed stones
-                let removedcolor = match player { White => Black, Black => White };
-                for mut grp in removed.move_iter() {
-                    let newgid = self.next_gid();
-                    for &(v,w) in grp.get_stones() {
-                        self.stones[v-1][w-1] = Stone(removedcolor, newgid);
-                    }
-                    grp.add_liberty(x, y);
-                    self.groups.insert(newgid, grp);
-                }
-                // check if last move was a ko
-                single_match!(self.history.back() :
-                    Some(&Move{player: _, move: Put(v, w), removed: ref removed}) => {
-                    if removed.len() == 1 && removed[0].stone_count() == 1 &&
-                       self.groups[self.gid_of_stone(v, w)].liberty_count() == 1 {
-                        self.current_ko = *removed[0].get_stones().next().unwrap();
-                    } else {
-                        self.current_ko = (0, 0);
-                    }
-                });
-                true
-            }
-        }
+    /// Japanese territory score, komi included: territory plus prisoners,
+    /// the groups of the given dead stones being removed as prisoners
+    /// first. Positive when Black is ahead.
+    pub fn territory_score(&self, dead: &[(uint, uint)]) -> f32 {
+        self.territory_scoring(dead).score
     }
 
-    // Removes the liberty 'killer' of group containing stone 'stone'.
-    // If it was the last liberty, the groups is removed and returned.
-    fn remove_liberty(&mut self, stone: (uint, uint), killer: (uint, uint)) -> Option<Group> {
-        let ((x,y),(kx,ky)) = (stone, killer);
-        match self.stones[x-1][y-1] {
-            Empty => None,
-            Stone(_, gid) => {
-                self.groups.find_mut(&gid).unwrap().remove_liberty(kx, ky);
-                if self.groups[gid].is_dead() {
-                    let grp = self.groups.pop(&gid).unwrap();
-                    // remove stones and add liberties to neighbors
-                    for &(v, w) in grp.get_stones() {
-                        self.stones[v-1][w-1] = Empty;
-                        Board::loop_over_neighbours(v, w, self.size, |a, b| {
-                            single_match!(self.stones[a-1][b-1] : Stone(_, grpid) => {
-                                if grpid != gid {
-                                    self.groups.find_mut(&grpid).unwrap().add_liberty(v,w);
-                                }
-                            });
-                        });
-                    }
-                    Some(grp)
-                } else {
-                    None
+    /// Territory score with the owner of every point, once the dead stones
+    /// are removed: the points of dead stones belong to the opponent if it
+    /// surrounds them, and the eyes of groups in seki to nobody.
+    pub fn territory_scoring(&self, dead: &[(uint, uint)]) -> Scoring {
+        let mut goban = self.clone_without_history();
+        for &(x, y) in dead.iter() {
+            single_match!(goban.point(x, y) : Stone(col, id) => {
+                let gid = goban.find(id);
+                let grp = goban.remove_group(gid);
+                match col {
+                    Black => goban.black_dead += grp.stone_count(),
+                    White => goban.white_dead += grp.stone_count()
                 }
-            }
+            });
+        }
+        let counts = goban.counts();
+        let mut ownership = goban.territory_map();
+        // eyes of groups in seki are not territory, an eye shared by two
+        // groups is only taken off once
+        let mut eyes = TreeSet::new();
+        for &gid in goban.seki_groups().iter() {
+            let colour = goban.group_colour(&goban.groups[gid]);
+            for (x, y) in goban.groups[gid].get_liberties() {
+                if goban.eye_kind(colour, x, y) != NotEye {
+                    eyes.insert((x, y));
+                }
+            }
+        }
+        let (mut black_eyes, mut white_eyes) = (0u, 0u);
+        for &(x, y) in eyes.iter() {
+            // only points counted as territory are taken off it
+            match ownership[x-1][y-1] {
+                Some(Black) => black_eyes += 1,
+                Some(White) => white_eyes += 1,
+                None => {}
+            }
+            ownership[x-1][y-1] = None;
+        }
+        let black = counts.black_territory - black_eyes + goban.white_dead;
+        let white = counts.white_territory - white_eyes + goban.black_dead;
+        Scoring { score: black as f32 - white as f32 - self.komi, ownership: ownership }
+    }
+
+    /// Result of the game according to the territory score.
+    pub fn territory_result(&self, dead: &[(uint, uint)]) -> GameResult {
+        GameResult::from_score(self.territory_score(dead))
+    }
+
+    /// Gids of the groups of the given colour which are unconditionally
+    /// alive according to Benson's algorithm: they cannot be captured
+    /// even if their owner passes every move.
+    pub fn pass_alive_groups(&self, colour: Colour) -> Vec<uint> {
+        // regions are the connected areas free of stones of the colour
+        let mut regions: Vec<Vec<(uint, uint)>> = Vec::new();
+        let mut visited = [[false, ..board_maxsize], ..board_maxsize];
+        let is_own = |x: uint, y: uint| match self.point(x, y) {
+            Stone(col, _) => col == colour,
+            Empty => false
+        };
+        for x in range(1u, self.size+1) {
+            for y in range(1u, self.size+1) {
+                if visited[x-1][y-1] || is_own(x, y) {
+                    continue;
+                }
+                let mut region = Vec::new();
+                let mut to_loop = vec!((x, y));
+                visited[x-1][y-1] = true;
+                while !to_loop.is_empty() {
+                    let (v, w) = to_loop.pop().unwrap();
+                    region.push((v, w));
+                    Board::loop_over_neighbours(v, w, self.size, |a, b| {
+                        if !visited[a-1][b-1] && !is_own(a, b) {
+                            visited[a-1][b-1] = true;
+                            to_loop.push((a, b));
+                        }
+                    });
+                }
+                regions.push(region);
+            }
+        }
+        let mut alive: Vec<uint> = self.groups.iter().filter(|&(_, grp)| {
+            let (x, y) = grp.get_stones().next().unwrap();
+            is_own(x, y)
+        }).map(|(gid, _)| gid).collect();
+        let mut healthy = Vec::from_elem(regions.len(), true);
+        loop {
+            // a group needs two healthy regions whose empty points are all
+            // its liberties
+            let before = alive.len();
+            alive.retain(|&gid| {
+                let grp = &self.groups[gid];
+                regions.iter().zip(healthy.iter()).filter(|&(region, &ok)| {
+                    ok && region.iter().all(|&(x, y)| self.point(x, y) != Empty || grp.has_liberty(x, y))
+                }).count() >= 2
+            });
+            if alive.len() == before {
+                return alive;
+            }
+            // regions bordering a removed group are no longer healthy
+            for (region, ok) in regions.iter().zip(healthy.mut_iter()) {
+                for &(x, y) in region.iter() {
+                    Board::loop_over_neighbours(x, y, self.size, |a, b| {
+                        single_match!(self.point(a, b) : Stone(col, id) => {
+                            if col == colour && !alive.contains(&self.find(id)) {
+                                *ok = false;
+                            }
+                        });
+                    });
+                }
+            }
+        }
+    }
+
+    /// Gids of the groups living in seki with an adjacent opponent group:
+    /// they share liberties, and each has no other liberty than its eyes,
+    /// the same number of them and at most one, so that neither side can
+    /// fill a shared liberty without being captured. This heuristic only
+    /// recognises seki between two groups.
+    pub fn seki_groups(&self) -> Vec<uint> {
+        let mut seki = Vec::new();
+        for (gid, grp) in self.groups.iter() {
+            let colour = self.group_colour(grp);
+            let mut opponents = SmallVec::new();
+            for (x, y) in grp.get_liberties() {
+                Board::loop_over_neighbours(x, y, self.size, |a, b| {
+                    single_match!(self.point(a, b) : Stone(col, id) => {
+                        let ogid = self.find(id);
+                        if col != colour && !opponents.iter().any(|&g| g == ogid) {
+                            opponents.push(ogid);
+                        }
+                    });
+                });
+            }
+            let in_seki = opponents.iter().any(|&ogid| {
+                let other = &self.groups[ogid];
+                let other_colour = match colour { Black => White, White => Black };
+                match (self.private_eyes(grp, colour, other), self.private_eyes(other, other_colour, grp)) {
+                    (Some(mine), Some(theirs)) => mine == theirs && mine <= 1
+                                                  && grp.liberty_count() >= 2 && other.liberty_count() >= 2,
+                    _ => false
+                }
+            });
+            if in_seki {
+                seki.push(gid);
+            }
+        }
+        seki
+    }
+
+    // Number of eyes of a group, if all its liberties not shared with the
+    // other group are eyes.
+    fn private_eyes(&self, grp: &Group, colour: Colour, other: &Group) -> Option<uint> {
+        let mut eyes = 0u;
+        for (x, y) in grp.get_liberties() {
+            if other.has_liberty(x, y) {
+                continue;
+            }
+            if self.eye_kind(colour, x, y) == NotEye {
+                return None;
+            }
+            eyes += 1;
+        }
+        Some(eyes)
+    }
+
+    /// Groups of the colour with a single liberty, as (gid, x, y) with the
+    /// coordinates of that liberty.
+    pub fn groups_in_atari(&self, colour: Colour) -> Vec<(uint, uint, uint)> {
+        self.groups.iter().filter(|&(_, grp)| grp.liberty_count() == 1 && self.group_colour(grp) == colour)
+                          .map(|(gid, grp)| {
+                              let (x, y) = grp.get_liberties().next().unwrap();
+                              (gid, x, y)
+                          }).collect()
+    }
+
+    /// Rough strength of the group with the given gid: its liberties
+    /// (counting at most 6), 4 for each of its true eyes up to two, and up
+    /// to 3 more when an adjacent opponent group has fewer liberties.
+    /// None if there is no such group.
+    pub fn group_strength(&self, gid: uint) -> Option<uint> {
+        let grp = match self.groups.find(&gid) {
+            Some(grp) => grp,
+            None => return None
+        };
+        let colour = self.group_colour(grp);
+        let eyes = grp.get_liberties().filter(|&(x, y)| self.eye_kind(colour, x, y) == TrueEye).count();
+        // liberties of the weakest adjacent opponent group
+        let mut weakest: Option<uint> = None;
+        for (x, y) in grp.get_stones() {
+            Board::loop_over_neighbours(x, y, self.size, |a, b| {
+                single_match!(self.point(a, b) : Stone(col, id) => {
+                    if col != colour {
+                        let libs = self.groups[self.find(id)].liberty_count();
+                        weakest = Some(weakest.map_or(libs, |w| min(w, libs)));
+                    }
+                });
+            });
+        }
+        let libs = grp.liberty_count();
+        let lead = match weakest {
+            Some(w) if w < libs => min(libs - w, 3),
+            _ => 0
+        };
+        Some(min(libs, 6) + 4 * min(eyes, 2) + lead)
+    }
+
+    fn group_colour(&self, grp: &Group) -> Colour {
+        let (x, y) = grp.get_stones().next().unwrap();
+        match self.point(x, y) {
+            Stone(col, _) => col,
+            Empty => unreachable!()
+        }
+    }
+
+    /// Classifies the point as an eye of the given colour: it must be empty
+    /// with only stones of the colour as neighbours, and the opponent must
+    /// hold at most one diagonal, none on the edge of the board.
+    pub fn eye_kind(&self, colour: Colour, x: uint, y: uint) -> EyeKind {
+        if self.point(x, y) != Empty {
+            return NotEye;
+        }
+        let mut surrounded = true;
+        Board::loop_over_neighbours(x, y, self.size, |a, b| {
+            surrounded = surrounded && match self.point(a, b) {
+                Stone(col, _) => col == colour,
+                Empty => false
+            };
+        });
+        if !surrounded {
+            return NotEye;
+        }
+        let (mut opponent, mut offboard) = (0u, 0u);
+        for &(dx, dy) in [(-1i, -1i), (-1, 1), (1, -1), (1, 1)].iter() {
+            let (a, b) = (x as int + dx, y as int + dy);
+            if a < 1 || b < 1 || a > self.size as int || b > self.size as int {
+                offboard += 1;
+                continue;
+            }
+            single_match!(self.point(a as uint, b as uint) : Stone(col, _) => {
+                if col != colour { opponent += 1; }
+            });
+        }
+        if opponent >= 2 || (offboard > 0 && opponent >= 1) {
+            FalseEye
+        } else {
+            TrueEye
+        }
+    }
+
+    /// Whether the point is a true eye of the given colour.
+    pub fn is_eye(&self, colour: Colour, x: uint, y: uint) -> bool {
+        self.eye_kind(colour, x, y) == TrueEye
+    }
+
+    /// Owner of every point: stones belong to their colour, and empty
+    /// regions to the only colour bordering them, if any.
+    pub fn territory_map(&self) -> OwnershipMap {
+        let mut map = [[None, ..board_maxsize], ..board_maxsize];
+        for (x, y, stone) in self.iter() {
+            map[x-1][y-1] = stone;
+        }
+        for region in self.empty_regions().iter() {
+            let owner = match (region.borders_black, region.borders_white) {
+                (true, false) => Some(Black),
+                (false, true) => Some(White),
+                _ => None
+            };
+            for &(x, y) in region.points.iter() {
+                map[x-1][y-1] = owner;
+            }
+        }
+        map
+    }
+
+    /// Neutral points (dame): the empty points of regions bordering both
+    /// colours, worth nothing under territory rules but a point to whoever
+    /// fills them under area rules. The liberties of groups in seki are
+    /// left out, filling them would get the stones captured.
+    pub fn dame(&self) -> Vec<(uint, uint)> {
+        let seki = self.seki_groups();
+        let mut dame = Vec::new();
+        for region in self.empty_regions().iter() {
+            if !(region.borders_black && region.borders_white) {
+                continue;
+            }
+            for &(x, y) in region.points.iter() {
+                if !seki.iter().any(|&gid| self.groups[gid].has_liberty(x, y)) {
+                    dame.push((x, y));
+                }
+            }
+        }
+        dame
+    }
+
+    // flood fills all the empty regions of the board
+    fn empty_regions(&self) -> Vec<EmptyRegion> {
+        let mut regions = Vec::new();
+        let mut visited = [[false, ..board_maxsize], ..board_maxsize];
+        for x in range(1u, self.size+1) {
+            for y in range(1u, self.size+1) {
+                if visited[x-1][y-1] || self.point(x, y) != Empty {
+                    continue;
+                }
+                let mut region = EmptyRegion { points: Vec::new(), borders_black: false, borders_white: false };
+                let mut to_loop = vec!((x, y));
+                visited[x-1][y-1] = true;
+                while !to_loop.is_empty() {
+                    let (v, w) = to_loop.pop().unwrap();
+                    region.points.push((v, w));
+                    Board::loop_over_neighbours(v, w, self.size, |a, b| {
+                        match self.point(a, b) {
+                            Stone(Black, _) => region.borders_black = true,
+                            Stone(White, _) => region.borders_white = true,
+                            Empty => if !visited[a-1][b-1] {
+                                visited[a-1][b-1] = true;
+                                to_loop.push((a, b));
+                            }
+                        }
+                    });
+                }
+                regions.push(region);
+            }
+        }
+        regions
+    }
+
+    /// Resets the board and clear the history. The board is then
+    /// ready for a new game.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.groups.clear();
+        self.links.clear();
+        self.stones = Vec::from_elem(self.size * self.size, Empty);
+        self.current_ko = (0, 0);
+        self.white_dead = 0;
+        self.black_dead = 0;
+        self.hash = 0;
+        self.start_hash = 0;
+        self.start_player = Black;
+        self.resigned = None;
+        self.compacted = 0;
+    }
+
+    /// Change the size of the board, must be between 1 and 25 inclusive.
+    pub fn resize(&mut self, newsize: uint) -> bool {
+        if newsize > 0 && newsize <= board_maxsize {
+            self.size = newsize;
+            self.clear();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns a copy of the board without history, can thus be used to think,
+    /// experiment and prepare the next move.
+    pub fn clone_without_history(&self) -> Board {
+        Board {
+            stones: self.stones.clone(),
+            history: DList::new(),
+            groups: self.groups.clone(),
+            links: self.links.clone(),
+            size: self.size,
+            white_dead: self.white_dead,
+            black_dead: self.black_dead,
+            current_ko: self.current_ko,
+            komi: self.komi,
+            ko_rule: self.ko_rule,
+            allow_suicide: self.allow_suicide,
+            hash: self.hash,
+            start_hash: self.hash,
+            start_player: self.to_move(),
+            resigned: self.resigned,
+            compacted: 0,
+            history_limit: self.history_limit,
+            // observers follow a single board
+            observers: Vec::new()
+        }
+    }
+
+    /// Copy of the position transformed by the symmetry, with the same
+    /// komi and rules but no history.
+    pub fn transformed(&self, symmetry: Symmetry) -> Board {
+        let mut goban = self.clone_without_history();
+        goban.clear();
+        for (x, y, stone) in self.iter() {
+            single_match!(stone : Some(colour) => {
+                let (a, b) = symmetry.apply(self.size, x, y);
+                // every group keeps a liberty, so no stone is refused
+                let placed = goban.place_setup_stone(colour, a, b);
+                debug_assert!(placed);
+            });
+        }
+        goban.start_player = self.to_move();
+        goban.current_ko = match self.get_current_ko() {
+            Some((x, y)) => symmetry.apply(self.size, x, y),
+            None => (0, 0)
+        };
+        goban.black_dead = self.black_dead;
+        goban.white_dead = self.white_dead;
+        goban
+    }
+
+    /// Symmetries leaving the position unchanged, Identity included. On
+    /// the empty board all 8 of them do, so only one move of each set of
+    /// symmetric moves needs to be considered.
+    pub fn symmetries(&self) -> Vec<Symmetry> {
+        symmetries.iter().map(|&s| s).filter(|s| {
+            let ko_kept = match self.get_current_ko() {
+                Some((x, y)) => s.apply(self.size, x, y) == (x, y),
+                None => true
+            };
+            ko_kept && self.iter().all(|(x, y, stone)| {
+                let (a, b) = s.apply(self.size, x, y);
+                self.at(a, b) == stone
+            })
+        }).collect()
+    }
+
+    /// The game as a record: the starting position as setup stones, and
+    /// the moves of the history. If the history was compacted, the record
+    /// starts after the compacted moves.
+    pub fn record(&self) -> GameRecord {
+        let size = self.size;
+        let kept = self.history.len() - self.compacted;
+        let mut points: Vec<Option<Colour>> = self.iter().map(|(_, _, stone)| stone).collect();
+        // the moves are taken back from the last one
+        for mv in self.history.iter().rev().take(kept) {
+            single_match!(mv.move : Put(x, y) => {
+                let removed = if mv.suicide {
+                    mv.player
+                } else {
+                    match mv.player { Black => White, White => Black }
+                };
+                for grp in mv.removed.iter() {
+                    for (a, b) in grp.get_stones() {
+                        *points.get_mut((a-1) * size + b-1) = Some(removed);
+                    }
+                }
+                *points.get_mut((x-1) * size + y-1) = None;
+            });
+        }
+        let mut record = GameRecord::new(size, self.komi);
+        for (i, point) in points.iter().enumerate() {
+            single_match!(*point : Some(colour) => {
+                record.setup.push((colour, i / size + 1, i % size + 1));
+            });
+        }
+        record.moves = self.history.iter().skip(self.compacted).map(|mv| (mv.player, mv.move)).collect();
+        record.start_player = Some(match record.moves.as_slice().head() {
+            Some(&(colour, _)) => colour,
+            None => self.to_move()
+        });
+        record
+    }
+
+    /// Registers an observer, notified of every move played or undone
+    /// from now on. Copies of the board have no observers.
+    pub fn add_observer(&mut self, observer: Box<BoardObserver + Send + Sync>) {
+        self.observers.push(observer);
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Limits the number of moves which can be undone. Older moves drop
+    /// the groups they took off the board, which hold most of the memory
+    /// of the history, and only keep the few words the ko rules and the
+    /// game record need. None, the default, keeps everything.
+    pub fn set_history_limit(&mut self, limit: Option<uint>) {
+        self.history_limit = limit;
+        self.compact_history();
+    }
+
+    pub fn get_history_limit(&self) -> Option<uint> {
+        self.history_limit
+    }
+
+    // frees the removed groups of the moves beyond the limit, in batches
+    // so that the history is not walked after every move
+    fn compact_history(&mut self) {
+        let limit = match self.history_limit {
+            Some(limit) => limit,
+            None => return
+        };
+        if self.history.len() - self.compacted <= 2 * limit {
+            return;
+        }
+        let keep_from = self.history.len() - limit;
+        for mv in self.history.mut_iter().skip(self.compacted).take(keep_from - self.compacted) {
+            mv.removed = Vec::new();
+        }
+        self.compacted = keep_from;
+    }
+
+    /// Drops the history, the current position becoming the starting
+    /// position of the game. Moves played before can no longer be undone.
+    pub fn forget_history(&mut self) {
+        self.start_player = self.to_move();
+        self.history.clear();
+        self.compacted = 0;
+        self.start_hash = self.hash;
+    }
+
+    /// Player whose turn it is: the opponent of the last player, or the
+    /// first player of the game, Black unless a handicap was given.
+    pub fn to_move(&self) -> Colour {
+        match self.history.back() {
+            Some(mv) => match mv.player { Black => White, White => Black },
+            None => self.start_player
+        }
+    }
+
+    /// Distance from the point to the nearest edge, 0 on the first line.
+    pub fn edge_distance(&self, x: uint, y: uint) -> uint {
+        let n = self.size + 1;
+        min(min(x, n - x), min(y, n - y)) - 1
+    }
+
+    /// Line of the point, counted from the nearest edge: 1 for the first
+    /// line, 3 for the third line, etc.
+    pub fn line(&self, x: uint, y: uint) -> uint {
+        self.edge_distance(x, y) + 1
+    }
+
+    /// Whether the point is in a corner area: on the first 5 lines of two
+    /// adjacent edges, or the first 3 below 13x13.
+    pub fn in_corner(&self, x: uint, y: uint) -> bool {
+        let n = self.size + 1;
+        let lines = if self.size >= 13 { 5 } else { 3 };
+        min(x, n - x) <= lines && min(y, n - y) <= lines
+    }
+
+    /// Conventional star points (hoshi) of the board: the corner points on
+    /// the 4th line, or the 3rd below 13x13, the center of odd boards, and
+    /// the middle of the sides of odd boards from 15x15. None below 7x7.
+    pub fn star_points(&self) -> Vec<(uint, uint)> {
+        let size = self.size;
+        if size < 7 {
+            return Vec::new();
+        }
+        let (low, high, mid) = Board::star_lines(size);
+        let mut points = vec!((low, low), (low, high), (high, low), (high, high));
+        if size % 2 == 1 {
+            if size >= 15 {
+                points.push_all([(low, mid), (mid, low), (mid, high), (high, mid)]);
+            }
+            points.push((mid, mid));
+        }
+        points
+    }
+
+    // lines of the corner star points and of the center
+    fn star_lines(size: uint) -> (uint, uint, uint) {
+        let low = if size >= 13 { 4 } else { 3 };
+        (low, size + 1 - low, (size + 1) / 2)
+    }
+
+    /// Points of the fixed handicap of the given number of stones, as
+    /// defined by the GTP specification, None if this number is not
+    /// allowed on the board.
+    pub fn fixed_handicap_points(&self, stones: uint) -> Option<Vec<(uint, uint)>> {
+        let size = self.size;
+        let max = if size >= 9 && size % 2 == 1 { 9 } else if size >= 7 { 4 } else { 0 };
+        if stones < 2 || stones > max {
+            return None;
+        }
+        let (low, high, mid) = Board::star_lines(size);
+        let mut points = vec!((low, low), (high, high), (low, high), (high, low));
+        if stones >= 6 {
+            points.push_all([(low, mid), (high, mid)]);
+        }
+        if stones >= 8 {
+            points.push_all([(mid, low), (mid, high)]);
+        }
+        points.truncate(if stones % 2 == 1 && stones >= 5 { stones - 1 } else { stones });
+        // odd handicaps from 5 stones get the center point
+        if stones % 2 == 1 && stones >= 5 {
+            points.push((mid, mid));
+        }
+        Some(points)
+    }
+
+    /// Puts a fixed handicap on the empty board. The stones are part of
+    /// the starting position rather than moves. Returns their points, or
+    /// None if the board is not empty or the handicap is not allowed.
+    pub fn place_fixed_handicap(&mut self, stones: uint) -> Option<Vec<(uint, uint)>> {
+        if !self.groups.is_empty() || !self.history.is_empty() {
+            return None;
+        }
+        let points = match self.fixed_handicap_points(stones) {
+            Some(p) => p,
+            None => return None
+        };
+        self.place_handicap(points.as_slice());
+        Some(points)
+    }
+
+    /// Chooses the points of a free handicap of the given number of stones:
+    /// the largest fixed handicap pattern that fits, completed with points
+    /// as far as possible from the stones already chosen and from the edge.
+    /// None if the board cannot hold that many stones.
+    pub fn free_handicap_points(&self, stones: uint) -> Option<Vec<(uint, uint)>> {
+        let size = self.size;
+        if stones < 2 || stones >= size * size {
+            return None;
+        }
+        let mut points = range(2, stones + 1).rev().filter_map(|n| self.fixed_handicap_points(n))
+                                             .next().unwrap_or(Vec::new());
+        let dist = |a: uint, b: uint| if a > b { a - b } else { b - a };
+        while points.len() < stones {
+            let mut best = (0u, 0u, 0u);
+            for x in range(1u, size+1) {
+                for y in range(1u, size+1) {
+                    if points.contains(&(x, y)) {
+                        continue;
+                    }
+                    let edge = min(min(x, y), min(size + 1 - x, size + 1 - y));
+                    let spacing = points.iter().map(|&(a, b)| dist(a, x) + dist(b, y)).min().unwrap_or(size);
+                    let score = min(spacing, 2 * edge);
+                    if score > best.val0() {
+                        best = (score, x, y);
+                    }
+                }
+            }
+            points.push((best.val1(), best.val2()));
+        }
+        Some(points)
+    }
+
+    /// Puts a free handicap on the empty board, on points chosen by
+    /// free_handicap_points. Returns them, or None if the board is not
+    /// empty or cannot hold that many stones.
+    pub fn place_free_handicap(&mut self, stones: uint) -> Option<Vec<(uint, uint)>> {
+        if !self.groups.is_empty() || !self.history.is_empty() {
+            return None;
+        }
+        let points = match self.free_handicap_points(stones) {
+            Some(p) => p,
+            None => return None
+        };
+        self.place_handicap(points.as_slice());
+        Some(points)
+    }
+
+    /// Puts a handicap chosen by the opponent or the server on the empty
+    /// board. Returns false, leaving the board untouched, if the board is
+    /// not empty or the points are not at least two distinct points of the
+    /// board leaving one of them empty.
+    pub fn set_free_handicap(&mut self, points: &[(uint, uint)]) -> bool {
+        if !self.groups.is_empty() || !self.history.is_empty()
+           || points.len() < 2 || points.len() >= self.size * self.size {
+            return false;
+        }
+        for (i, &(x, y)) in points.iter().enumerate() {
+            if x == 0 || y == 0 || x > self.size || y > self.size || points.slice_to(i).contains(&(x, y)) {
+                return false;
+            }
+        }
+        self.place_handicap(points);
+        true
+    }
+
+    // handicap stones are part of the starting position, and White plays
+    // first after them
+    fn place_handicap(&mut self, points: &[(uint, uint)]) {
+        for &(x, y) in points.iter() {
+            self.place_setup_stone(Black, x, y);
+        }
+        self.start_player = White;
+    }
+
+    /// Remembers the current position so that a search can play moves on
+    /// this board and come back to it with restore, instead of cloning
+    /// the board for every node.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint { moves: self.history.len(), hash: self.hash }
+    }
+
+    /// Undoes the moves played since the checkpoint, which must have been
+    /// taken on this board, undo being the exact inverse of a move. Returns
+    /// false, leaving the board untouched, if moves before the checkpoint
+    /// were undone in between (even if other moves were played since), can
+    /// no longer be undone, or if a player resigned since.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> bool {
+        // undo would take back the resignation rather than a move
+        if self.resigned.is_some() || self.history.len() < checkpoint.moves
+           || checkpoint.moves < self.compacted {
+            return false;
+        }
+        // the position the undos would lead to, which must be the one of
+        // the checkpoint
+        let hash = if checkpoint.moves == 0 {
+            self.start_hash
+        } else {
+            self.history.iter().nth(checkpoint.moves - 1).unwrap().hash
+        };
+        if hash != checkpoint.hash {
+            return false;
+        }
+        while self.history.len() > checkpoint.moves {
+            if !self.undo() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns an immutable copy of the current position, without history,
+    /// that can be sent to and shared by other tasks.
+    pub fn snapshot(&self) -> Snapshot {
+        Arc::new(self.clone_without_history())
+    }
+
+    /// Compact description of the position: the rows from top to bottom
+    /// as '.', 'X' and 'O' separated by '/', the player to move, the ko
+    /// point or '-', and the black and white stones captured so far,
+    /// e.g. "..X/.O./... W - 0 1".
+    pub fn to_position_string(&self) -> String {
+        let rows: Vec<String> = range(0, self.size).map(|i| {
+            let y = self.size - i;
+            range(1, self.size + 1).map(|x| match self.point(x, y) {
+                Stone(Black, _) => 'X',
+                Stone(White, _) => 'O',
+                Empty => '.'
+            }).collect()
+        }).collect();
+        let ko = match self.get_current_ko() {
+            Some((x, y)) => coords::format_vertex(x, y),
+            None => String::from_str("-")
+        };
+        format!("{} {} {} {} {}", rows.connect("/"), self.to_move(), ko, self.black_dead, self.white_dead)
+    }
+
+    /// Reads back a position written by to_position_string, the board has
+    /// no history.
+    pub fn from_position_string(position: &str) -> Result<Board, String> {
+        let fields: Vec<&str> = position.words().collect();
+        let (rows, to_move, ko, black_dead, white_dead) = match fields.as_slice() {
+            [rows, to_move, ko, black_dead, white_dead] => (rows, to_move, ko, black_dead, white_dead),
+            _ => return Err(format!("expected 5 fields in \"{}\"", position))
+        };
+        let rows: Vec<&str> = rows.split('/').collect();
+        let size = rows.len();
+        let mut goban = Board::new();
+        if !goban.resize(size) {
+            return Err(format!("invalid board size {}", size));
+        }
+        for (i, row) in rows.iter().enumerate() {
+            if row.char_len() != size {
+                return Err(format!("row {} has {} points instead of {}", i + 1, row.char_len(), size));
+            }
+            let y = size - i;
+            for (j, c) in row.chars().enumerate() {
+                let colour = match c {
+                    'X' => Black,
+                    'O' => White,
+                    '.' => continue,
+                    _ => return Err(format!("invalid character '{}' in row {}", c, i + 1))
+                };
+                if !goban.place_setup_stone(colour, j + 1, y) {
+                    return Err(format!("stone at {} has no liberty", coords::format_vertex(j + 1, y)));
+                }
+            }
+        }
+        goban.start_player = match to_move {
+            "B" => Black,
+            "W" => White,
+            _ => return Err(format!("invalid player to move \"{}\"", to_move))
+        };
+        goban.current_ko = match (ko, coords::parse_vertex(ko)) {
+            ("-", _) => (0, 0),
+            (_, Some((x, y))) if x <= size && y <= size && goban.point(x, y) == Empty => (x, y),
+            _ => return Err(format!("invalid ko point \"{}\"", ko))
+        };
+        match (from_str::<uint>(black_dead), from_str::<uint>(white_dead)) {
+            (Some(b), Some(w)) => {
+                goban.black_dead = b;
+                goban.white_dead = w;
+            }
+            _ => return Err(format!("invalid capture counts \"{} {}\"", black_dead, white_dead))
+        }
+        Ok(goban)
+    }
+
+    /// Builds a position from a diagram: one line per row, top row first,
+    /// with 'X' for black stones, 'O' for white stones and '.' or '+' for
+    /// empty points. Spaces and blank lines are ignored. The stones are
+    /// setup stones, the board has no history and Black is to move.
+    ///
+    /// ```text
+    /// . X O
+    /// X O .
+    /// . X O
+    /// ```
+    pub fn from_diagram(diagram: &str) -> Result<Board, String> {
+        let rows: Vec<Vec<char>> = diagram.lines()
+                                          .map(|line| line.chars().filter(|c| !c.is_whitespace()).collect())
+                                          .filter(|row: &Vec<char>| !row.is_empty())
+                                          .collect();
+        let size = rows.len();
+        let mut goban = Board::new();
+        if !goban.resize(size) {
+            return Err(format!("invalid diagram size {}", size));
+        }
+        for (i, row) in rows.iter().enumerate() {
+            if row.len() != size {
+                return Err(format!("diagram row {} has {} points instead of {}", i + 1, row.len(), size));
+            }
+            let y = size - i;
+            for (j, &c) in row.iter().enumerate() {
+                let colour = match c {
+                    'X' => Black,
+                    'O' => White,
+                    '.' | '+' => continue,
+                    _ => return Err(format!("invalid character '{}' in diagram", c))
+                };
+                if !goban.place_setup_stone(colour, j + 1, y) {
+                    return Err(format!("stone at {} has no liberty", coords::format_vertex(j + 1, y)));
+                }
+            }
+        }
+        goban.start_player = Black;
+        Ok(goban)
+    }
+
+    /// Binary encoding of the position, much smaller than the board: the
+    /// size, the player to move (0 for Black, 1 for White), the ko point
+    /// (0, 0 if none), then the points column by column using 2 bits each,
+    /// 0 for empty, 1 for Black and 2 for White, starting from the low
+    /// bits of every byte. Captures and history are not kept.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec!(self.size as u8, if self.to_move() == White { 1 } else { 0 },
+                             self.current_ko.val0() as u8, self.current_ko.val1() as u8);
+        for (i, (_, _, stone)) in self.iter().enumerate() {
+            if i % 4 == 0 {
+                bytes.push(0);
+            }
+            let bits = match stone {
+                None => 0u8,
+                Some(Black) => 1,
+                Some(White) => 2
+            };
+            *bytes.mut_last().unwrap() |= bits << (2 * (i % 4));
+        }
+        bytes
+    }
+
+    /// Reads back a position written by encode, the board has no history.
+    pub fn decode(bytes: &[u8]) -> Result<Board, String> {
+        if bytes.len() < 4 {
+            return Err(String::from_str("truncated position"));
+        }
+        let size = bytes[0] as uint;
+        let mut goban = Board::new();
+        if !goban.resize(size) {
+            return Err(format!("invalid board size {}", size));
+        }
+        if bytes.len() != 4 + (size * size + 3) / 4 {
+            return Err(format!("expected {} bytes for size {}, got {}",
+                               4 + (size * size + 3) / 4, size, bytes.len()));
+        }
+        for i in range(0, size * size) {
+            let (x, y) = (i / size + 1, i % size + 1);
+            let colour = match (bytes[4 + i / 4] >> (2 * (i % 4))) & 3 {
+                0 => continue,
+                1 => Black,
+                2 => White,
+                _ => return Err(format!("invalid point {}", coords::format_vertex(x, y)))
+            };
+            if !goban.place_setup_stone(colour, x, y) {
+                return Err(format!("stone at {} has no liberty", coords::format_vertex(x, y)));
+            }
+        }
+        goban.start_player = match bytes[1] {
+            0 => Black,
+            1 => White,
+            p => return Err(format!("invalid player to move {}", p))
+        };
+        goban.current_ko = match (bytes[2] as uint, bytes[3] as uint) {
+            (0, 0) => (0, 0),
+            (x, y) if x >= 1 && y >= 1 && x <= size && y <= size && goban.point(x, y) == Empty => (x, y),
+            (x, y) => return Err(format!("invalid ko point ({},{})", x, y))
+        };
+        Ok(goban)
+    }
+
+    // content of a point, the coordinates start at 1
+    fn point(&self, x: uint, y: uint) -> Intersection {
+        self.stones[(x-1) * self.size + y-1]
+    }
+
+    fn set_point(&mut self, x: uint, y: uint, content: Intersection) {
+        let size = self.size;
+        *self.stones.get_mut((x-1) * size + y-1) = content;
+    }
+
+    fn loop_over_neighbours(x:uint, y:uint, size:uint, func: |uint, uint|  -> ()) {
+        if x > 1 { func(x-1, y); }
+        if y > 1 { func(x, y-1); }
+        if x < size { func(x+1, y); }
+        if y < size { func(x, y+1); }
+    }
+
+    // Group of a stone id. Absorbed groups always lead to a bigger one, so
+    // chains of links stay logarithmic.
+    fn find(&self, id: uint) -> uint {
+        let mut gid = id;
+        loop {
+            match self.links.find(&gid) {
+                Some(&next) => gid = next,
+                None => return gid
+            }
+        }
+    }
+
+    // shall be called only if you KNOW a stone is there
+    fn gid_of_stone(&self, x:uint, y:uint) -> uint {
+        match self.point(x, y) {
+            Stone(_, id) => self.find(id),
+            _ => unreachable!()
+        }
+    }
+
+    // we want to reuse old keys
+    fn next_gid(&self) -> uint {
+        let mut key = 0u;
+        while self.groups.contains_key(&key) || self.links.contains_key(&key) { key += 1u; }
+        key
+    }
+
+    // The stones of a group which is dismantled get new ids, the links
+    // of their old ids are dropped. They all belong to that group.
+    fn unlink_stones(&mut self, grp: &Group) {
+        for (v, w) in grp.get_stones() {
+            single_match!(self.point(v, w) : Stone(_, id) => {
+                self.links.remove(&id);
+            });
+        }
+    }
+
+    fn split_group(&mut self, gid: uint, unput: (uint,uint)) {
+        let oldgroup = self.groups.pop(&gid).unwrap();
+        self.unlink_stones(&oldgroup);
+        self.set_point(unput.val0(), unput.val1(), Empty);
+        let mut oldstones = oldgroup.dismantle();
+        oldstones.remove(unput.val0(), unput.val1());
+        while !oldstones.is_empty() {
+            // retrieve a random item
+            let (x, y) = oldstones.iter().next().unwrap();
+            let newgid = self.next_gid();
+            self.groups.insert(newgid, Group::new());
+            let ref mut newgroup = self.groups.find_mut(&newgid).unwrap();
+            let size = self.size;
+            let mut to_loop = SmallVec::new();
+            // loop preparation
+            to_loop.push((x,y));
+            oldstones.remove(x, y);
+            // loop on the whole groupe
+            while !to_loop.is_empty() {
+                let (v, w) = to_loop.pop().unwrap();
+                {
+                    let point = self.stones.get_mut((v-1) * size + w-1);
+                    *point = match *point {
+                        Stone(col, _) => Stone(col, newgid),
+                        _ => unreachable!() // same here
+                    };
+                }
+                newgroup.add_stone(v, w);
+                // neighbours are gathered first, so that the closure does
+                // not borrow the stones
+                let mut neighbours = SmallVec::new();
+                Board::loop_over_neighbours(v, w, size, |a, b| neighbours.push((a, b)));
+                for &(a, b) in neighbours.iter() {
+                    if oldstones.contains(a, b) {
+                        to_loop.push((a,b));
+                        oldstones.remove(a, b);
+                    } else if self.stones[(a-1) * size + b-1] == Empty {
+                        newgroup.add_liberty(a,b);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Undo the last move, or the resignation which ended the game.
+    pub fn undo(&mut self) -> bool {
+        if self.resigned.is_some() {
+            self.resigned = None;
+            return true;
+        }
+        if self.history.len() == self.compacted {
+            return false;
+        }
+        let previous_ko = match self.history.back() {
+            Some(mv) => mv.previous_ko,
+            None => None
+        };
+        let last = self.history.back().map(|mv| (mv.player, mv.move));
+        let undone = match self.history.pop() {
+            None => false,
+            Some(Move{move: Pass, ..}) => true,
+            Some(Move{player: player, move: Put(x,y), removed: removed, suicide: suicide, ..}) => {
+                // the removed stones are no longer prisoners
+                let count = removed.iter().fold(0, |n, grp| n + grp.stone_count());
+                match (player, suicide) {
+                    (White, false) | (Black, true) => self.black_dead -= count,
+                    (Black, false) | (White, true) => self.white_dead -= count
+                }
+                if suicide {
+                    // the suicided group comes back with the stone of the
+                    // move, which is then taken off as usual
+                    for grp in removed.move_iter() {
+                        let gid = self.restore_group(player, grp);
+                        self.split_group(gid, (x,y));
+                    }
+                } else {
+                    let oldgid = self.gid_of_stone(x, y);
+                    self.split_group(oldgid, (x,y));
+                    // restore removed stones
+                    let removedcolor = match player { White => Black, Black => White };
+                    for mut grp in removed.move_iter() {
+                        grp.add_liberty(x, y);
+                        self.restore_group(removedcolor, grp);
+                    }
+                }
+                // the point of the move is a liberty again for the opponent
+                Board::loop_over_neighbours(x, y, self.size, |a, b| {
+                    single_match!(self.point(a, b) : Stone(col, id) => {
+                        if col != player {
+                            let grpid = self.find(id);
+                            self.groups.find_mut(&grpid).unwrap().add_liberty(x, y);
+                        }
+                    });
+                });
+                true
+            }
+        };
+        // the ko state is the one from before the undone move, including
+        // a ko lifted by a pass
+        if undone {
+            self.current_ko = previous_ko.unwrap_or((0, 0));
+            self.hash = match self.history.back() {
+                Some(mv) => mv.hash,
+                None => self.start_hash
+            };
+            let (player, move) = last.unwrap();
+            for observer in self.observers.mut_iter() {
+                observer.on_undo(player, move);
+            }
+        }
+        self.check_invariants("undo");
+        undone
+    }
+
+    /// Number of steps undo can take back: the moves of the history which
+    /// were not compacted, and the resignation.
+    pub fn undoable_moves(&self) -> uint {
+        let resignation = if self.resigned.is_some() { 1 } else { 0 };
+        self.history.len() - self.compacted + resignation
+    }
+
+    /// Takes back the last n moves, all or none: returns false, leaving the
+    /// board untouched, if fewer than n can be undone. The ko point and the
+    /// prisoners are the ones from before the earliest undone move.
+    pub fn undo_n(&mut self, n: uint) -> bool {
+        if n > self.undoable_moves() {
+            return false;
+        }
+        for _ in range(0, n) {
+            let undone = self.undo();
+            debug_assert!(undone);
+        }
+        true
+    }
+
+    // Puts back on the board a group removed by a move, its stones are
+    // taken off the liberties of the neighbouring groups of the other
+    // colour. Returns the new gid of the group.
+    fn restore_group(&mut self, colour: Colour, grp: Group) -> uint {
+        let newgid = self.next_gid();
+        let stones: Vec<(uint, uint)> = grp.get_stones().collect();
+        for &(v,w) in stones.iter() {
+            self.set_point(v, w, Stone(colour, newgid));
+        }
+        self.groups.insert(newgid, grp);
+        for &(v,w) in stones.iter() {
+            Board::loop_over_neighbours(v, w, self.size, |a, b| {
+                single_match!(self.point(a, b) : Stone(col, id) => {
+                    if col != colour {
+                        let grpid = self.find(id);
+                        self.groups.find_mut(&grpid).unwrap().remove_liberty(v, w);
+                    }
+                });
+            });
+        }
+        newgid
+    }
+
+    // Takes a whole group off the board, its stones become liberties of
+    // the neighbouring groups.
+    fn remove_group(&mut self, gid: uint) -> Group {
+        let grp = self.groups.pop(&gid).unwrap();
+        self.unlink_stones(&grp);
+        for (v, w) in grp.get_stones() {
+            self.set_point(v, w, Empty);
+        }
+        for (v, w) in grp.get_stones() {
+            Board::loop_over_neighbours(v, w, self.size, |a, b| {
+                single_match!(self.point(a, b) : Stone(_, id) => {
+                    let grpid = self.find(id);
+                    self.groups.find_mut(&grpid).unwrap().add_liberty(v,w);
+                });
+            });
+        }
+        grp
+    }
+
+    // Removes the liberty 'killer' of group containing stone 'stone'.
+    // If it was the last liberty, the groups is removed and returned.
+    fn remove_liberty(&mut self, stone: (uint, uint), killer: (uint, uint)) -> Option<Group> {
+        let ((x,y),(kx,ky)) = (stone, killer);
+        match self.point(x, y) {
+            Empty => None,
+            Stone(_, id) => {
+                let gid = self.find(id);
+                self.groups.find_mut(&gid).unwrap().remove_liberty(kx, ky);
+                if self.groups[gid].is_dead() {
+                    Some(self.remove_group(gid))
+                } else {
+                    None
+                }
+            }
         }
     }
 
     fn fuse_groups(&mut self, x1: uint, y1: uint, x2: uint, y2: uint) {
         // gid1 shall be the biggest group
-        let (gid1, gid2) = match (self.stones[x1-1][y1-1], self.stones[x2-1][y2-1]) {
-            (Stone(col1, g1), Stone(col2, g2)) if g1 != g2  && col1 == col2 => {
+        let (gid1, gid2) = match (self.point(x1, y1), self.point(x2, y2)) {
+            (Stone(col1, id1), Stone(col2, id2)) if col1 == col2 => {
+                let (g1, g2) = (self.find(id1), self.find(id2));
+                if g1 == g2 {
+                    return;
+                }
                 if self.groups[g1].stone_count() > self.groups[g2].stone_count() {
                     (g1, g2)
                 } else {
@@ -336,41 +2006,243 @@ impl Board {
             }
             _ => { return }
         };
+        // no stone is repainted, the smaller group now leads to the other
         let oldgroup = self.groups.pop(&gid2).unwrap();
-        for &(v,w) in oldgroup.get_stones() {
-            single_match!(self.stones[v-1][w-1] : Stone(col, _) => {
-                self.stones[v-1][w-1] = Stone(col, gid1);
-            });
-        }
+        self.links.insert(gid2, gid1);
         self.groups.find_mut(&gid1).unwrap().absorb(oldgroup);
     }
 
-    /// The chosen player passes his turn
+    // Ko point created by the stone just put at (x, y) capturing 'removed':
+    // a single stone capturing a single stone and left with one liberty.
+    fn ko_point(&self, x: uint, y: uint, removed: &SmallVec<Group>) -> (uint, uint) {
+        // as we may have fused groups, our gid is not known
+        let grp = &self.groups[self.gid_of_stone(x, y)];
+        if removed.len() == 1 && removed[0].stone_count() == 1 &&
+           grp.stone_count() == 1 && grp.liberty_count() == 1 {
+            removed[0].get_stones().next().unwrap()
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// Plays a stone or a pass.
+    pub fn play_move(&mut self, player: Colour, move: Vertex) -> Result<(), IllegalMove> {
+        match move {
+            Put(x, y) => self.play(player, x, y),
+            Pass => { self.pass(player); Ok(()) }
+        }
+    }
+
+    /// Plays the moves in order, all or none: at the first illegal move,
+    /// the moves already played are undone and its index is returned with
+    /// the reason. The history is not compacted before the end of the
+    /// sequence, so that it can always be taken back. Nothing is played
+    /// once a player resigned.
+    pub fn play_sequence(&mut self, moves: &[(Colour, Vertex)]) -> Result<(), (uint, IllegalMove)> {
+        // undo would take back the resignation rather than a move
+        if self.resigned.is_some() && !moves.is_empty() {
+            return Err((0, GameOver));
+        }
+        let limit = self.history_limit.take();
+        let mut result = Ok(());
+        for (i, &(player, move)) in moves.iter().enumerate() {
+            match self.play_move(player, move) {
+                Ok(()) => {}
+                Err(reason) => {
+                    debug_assert!(i <= self.undoable_moves());
+                    for _ in range(0, i) {
+                        let undone = self.undo();
+                        debug_assert!(undone);
+                    }
+                    result = Err((i, reason));
+                    break;
+                }
+            }
+        }
+        self.history_limit = limit;
+        self.compact_history();
+        result
+    }
+
+    /// The chosen player passes his turn, which lifts any ko restriction.
     pub fn pass(&mut self, player: Colour) {
+        let previous_ko = self.get_current_ko();
+        self.current_ko = (0, 0);
         self.history.push(Move{
                 player: player,
                 move: Pass,
-                removed: Vec::new()
+                removed: Vec::new(),
+                suicide: false,
+                ko: None,
+                previous_ko: previous_ko,
+                time_spent: None,
+                cpu_time_spent: None,
+                hash: self.hash
             });
+        for observer in self.observers.mut_iter() {
+            observer.on_play(player, Pass);
+        }
+        self.compact_history();
+        self.check_invariants("pass");
+    }
+
+    // Hash of the position after player puts a stone at (x, y), which
+    // must be empty.
+    fn hash_after(&self, player: Colour, x: uint, y: uint) -> u64 {
+        let mut hash = self.hash ^ zobrist_key(player, x, y);
+        let mut captured = SmallVec::new();
+        let mut friends = SmallVec::new();
+        let mut liberty = false;
+        Board::loop_over_neighbours(x, y, self.size, |a, b| {
+            match self.point(a, b) {
+                Empty => liberty = true,
+                Stone(col, id) if col != player => {
+                    let gid = self.find(id);
+                    if self.groups[gid].liberty_count() == 1 && !captured.iter().any(|&g| g == gid) {
+                        captured.push(gid);
+                        for (v, w) in self.groups[gid].get_stones() {
+                            hash ^= zobrist_key(col, v, w);
+                        }
+                    }
+                }
+                Stone(_, id) => {
+                    let gid = self.find(id);
+                    liberty = liberty || self.groups[gid].liberty_count() > 1;
+                    if !friends.iter().any(|&g| g == gid) {
+                        friends.push(gid);
+                    }
+                }
+            }
+        });
+        if !liberty && captured.is_empty() {
+            // suicide, the stone leaves with its friendly neighbours
+            hash ^= zobrist_key(player, x, y);
+            for &gid in friends.iter() {
+                for (v, w) in self.groups[gid].get_stones() {
+                    hash ^= zobrist_key(player, v, w);
+                }
+            }
+        }
+        hash
+    }
+
+    // whether the move of player reaching a position of the given hash
+    // repeats an earlier position of the game under the superko rules
+    fn is_repetition(&self, player: Colour, hash: u64) -> bool {
+        let situational = self.ko_rule == SituationalSuperko;
+        // the starting position was to be played by the first player
+        let first_player = self.history.front().map_or(player, |mv| mv.player);
+        (hash == self.start_hash && (!situational || first_player != player))
+            || self.history.iter().any(|mv| mv.hash == hash && (!situational || mv.player == player))
+    }
+
+    /// Whether the player can put a stone at the given point, according
+    /// to the suicide and ko rules of the board. Nothing is modified.
+    pub fn is_legal(&self, player: Colour, x: uint, y: uint) -> bool {
+        self.check_move(player, x, y).is_ok()
+    }
+
+    /// Same as is_legal, but tells why an illegal move is.
+    pub fn check_move(&self, player: Colour, x: uint, y: uint) -> Result<(), IllegalMove> {
+        if x == 0 || y == 0 || x > self.size || y > self.size {
+            return Err(OutOfBounds);
+        }
+        if self.point(x, y) != Empty {
+            return Err(Occupied);
+        }
+        if (x, y) == self.current_ko {
+            return Err(Ko);
+        }
+        let mut alive = false;
+        let mut friendly = false;
+        Board::loop_over_neighbours(x, y, self.size, |a, b| {
+            match self.point(a, b) {
+                Empty => alive = true,
+                Stone(col, id) if col == player => {
+                    friendly = true;
+                    alive = alive || self.groups[self.find(id)].liberty_count() > 1;
+                }
+                // capturing a group gives liberties
+                Stone(_, id) => alive = alive || self.groups[self.find(id)].liberty_count() == 1
+            }
+        });
+        // the suicide of a lone stone would not change the position,
+        // it is never allowed
+        if !alive && !(self.allow_suicide && friendly) {
+            return Err(Suicide);
+        }
+        if self.ko_rule != SimpleKo && self.is_repetition(player, self.hash_after(player, x, y)) {
+            return Err(Ko);
+        }
+        Ok(())
     }
 
-    /// Plays the given move, will return false if the move cannot be played
-    /// (either because there is already a stone, or the stone would be dead,
-    /// or it is a simple ko).
-    pub fn play(&mut self, player: Colour, x: uint, y: uint) -> bool {
-        if self.stones[x-1][y-1] != Empty || (x, y) == self.current_ko {
-            // move is not possible
+    /// Whether a stone of the player at the given point would leave its
+    /// group with a single liberty, once the stones it captures are
+    /// removed. Legality is not checked.
+    pub fn is_self_atari(&self, player: Colour, x: uint, y: uint) -> bool {
+        let mut stones = Bitboard::new();
+        let mut liberties = Bitboard::new();
+        let mut captured: SmallVec<uint> = SmallVec::new();
+        stones.insert(x, y);
+        Board::loop_over_neighbours(x, y, self.size, |a, b| {
+            match self.point(a, b) {
+                Empty => liberties.insert(a, b),
+                Stone(col, id) => {
+                    let gid = self.find(id);
+                    let grp = &self.groups[gid];
+                    if col == player {
+                        stones.union_with(&grp.stones);
+                        liberties.union_with(&grp.liberties);
+                    } else if grp.liberty_count() == 1 && !captured.iter().any(|&g| g == gid) {
+                        captured.push(gid);
+                    }
+                }
+            }
+        });
+        liberties.remove(x, y);
+        if liberties.len() > 1 {
             return false;
         }
-        // put the stone
+        // captured stones next to the group become its liberties
+        for &gid in captured.iter() {
+            for (a, b) in self.groups[gid].get_stones() {
+                let mut touching = false;
+                Board::loop_over_neighbours(a, b, self.size, |c, d| {
+                    touching = touching || stones.contains(c, d);
+                });
+                if touching {
+                    liberties.insert(a, b);
+                }
+            }
+        }
+        liberties.len() == 1
+    }
+
+    /// All the points where the player can legally put a stone.
+    pub fn legal_moves(&self, player: Colour) -> Vec<(uint, uint)> {
+        let mut moves = Vec::new();
+        for x in range(1u, self.size+1) {
+            for y in range(1u, self.size+1) {
+                if self.is_legal(player, x, y) {
+                    moves.push((x, y));
+                }
+            }
+        }
+        moves
+    }
+
+    // Puts the stone, removing the opponent groups it captures, and returns
+    // them. The stone is fused with its friendly neighbours.
+    fn put_stone(&mut self, player: Colour, x: uint, y: uint) -> SmallVec<Group> {
         let gid = self.next_gid();
-        self.stones[x-1][y-1] = Stone(player, gid);
+        self.set_point(x, y, Stone(player, gid));
         self.groups.insert(gid, Group::new());
         self.groups.find_mut(&gid).unwrap().add_stone(x,y);
         // are we killing enemies_stones ?
-        let mut killed = Vec::new();
+        let mut killed = SmallVec::new();
         Board::loop_over_neighbours(x, y, self.size, |a, b| {
-            single_match!(self.stones[a-1][b-1] : Stone(col, _) => {
+            single_match!(self.point(a, b) : Stone(col, _) => {
                 if col != player {
                     single_match!(self.remove_liberty((a,b),(x,y)) : Some(grp) => {
                         killed.push(grp);
@@ -378,44 +2250,29 @@ impl Board {
                 }
             });
         });
-        if killed.len() == 0 {
-            // the move might be invalid, we must be more careful
-            let mut alive = false;
-            Board::loop_over_neighbours(x, y, self.size, |a, b| {
-                alive = alive || match self.stones[a-1][b-1] {
-                Empty => true,
-                Stone(col, gid) if col == player => self.groups[gid].liberty_count() > 1,
-                _ => false
-                }
-            });
-            if !alive {
-                // we should not have played this
-                self.stones[x-1][y-1] = Empty;
-                self.groups.remove(&gid);
-                // restore liberties
-                Board::loop_over_neighbours(x, y, self.size, |a, b| {
-                    single_match!(self.stones[a-1][b-1]: Stone(col, tmpid) => {
-                        if col != player {
-                            self.groups.find_mut(&tmpid).unwrap().add_liberty(x,y);
-                        }
-                    });
-                });
-                return false;
-            }
-        }
-        // okay, we live, let's clean up
         // does this stone have liberties ?
         Board::loop_over_neighbours(x, y, self.size, |a, b| {
-            single_match!(self.stones[a-1][b-1] : Empty => {
+            single_match!(self.point(a, b) : Empty => {
                 self.groups.find_mut(&gid).unwrap().add_liberty(a,b);
             });
         });
         // fuse groups
         Board::loop_over_neighbours(x, y, self.size, |a, b| {
-            single_match!(self.stones[a-1][b-1] : Stone(col, _) => {
+            single_match!(self.point(a, b) : Stone(col, _) => {
                 if col == player { self.fuse_groups(x,y,a,b); }
             });
         });
+        killed
+    }
+
+    /// Plays the given move, or tells why it cannot be played: there is
+    /// already a stone, the stone would be dead and suicide is not allowed,
+    /// or it is forbidden by the ko rule.
+    pub fn play(&mut self, player: Colour, x: uint, y: uint) -> Result<(), IllegalMove> {
+        try!(self.check_move(player, x, y));
+        let new_hash = self.hash_after(player, x, y);
+        let previous_ko = self.get_current_ko();
+        let mut killed = self.put_stone(player, x, y);
         //count dead stones
         for grp in killed.iter() {
             match player {
@@ -423,21 +2280,359 @@ impl Board {
                 Black => { self.white_dead += grp.stone_count(); }
             }
         }
+        // as we may have fused groups, our gid is not known
+        let newgid = self.gid_of_stone(x, y);
+        let suicide = self.groups[newgid].is_dead();
+        if suicide {
+            // the whole group of the stone is taken off the board
+            let grp = self.remove_group(newgid);
+            match player {
+                White => { self.white_dead += grp.stone_count(); }
+                Black => { self.black_dead += grp.stone_count(); }
+            }
+            killed.push(grp);
+        }
         // check ko
-        // as we fused groups, our gid is no longer known
-        let mygid = self.gid_of_stone(x, y);
-        if killed.len() == 1 && killed[0].stone_count() == 1 &&
-           self.groups[mygid].stone_count() == 1 {
-            self.current_ko = *killed[0].get_stones().next().unwrap();
-        } else {
-            self.current_ko = (0, 0);
+        self.current_ko = if suicide { (0, 0) } else { self.ko_point(x, y, &killed) };
+        self.hash = new_hash;
+        if !self.observers.is_empty() {
+            let captured = if suicide { player } else { match player { Black => White, White => Black } };
+            for observer in self.observers.mut_iter() {
+                observer.on_play(player, Put(x, y));
+            }
+            for grp in killed.iter() {
+                let stones: Vec<(uint, uint)> = grp.get_stones().collect();
+                for observer in self.observers.mut_iter() {
+                    observer.on_capture(captured, stones.as_slice());
+                }
+            }
         }
         // save history
         self.history.push(Move{
             player: player,
             move: Put(x,y),
-            removed: killed
+            removed: killed.move_iter().collect(),
+            suicide: suicide,
+            ko: self.get_current_ko(),
+            previous_ko: previous_ko,
+            time_spent: None,
+            cpu_time_spent: None,
+            hash: new_hash
+        });
+        self.compact_history();
+        self.check_invariants("play");
+        Ok(())
+    }
+
+    /// Puts a setup stone, as given by SGF AB/AW properties or a handicap,
+    /// outside of the alternation of moves. The resulting position becomes
+    /// the starting position of the game, so earlier moves can no longer be
+    /// undone. Returns false if the point is not empty, or if the stone
+    /// would capture or have no liberty, as setup stones never do.
+    pub fn place_setup_stone(&mut self, colour: Colour, x: uint, y: uint) -> bool {
+        if x == 0 || y == 0 || x > self.size || y > self.size || self.point(x, y) != Empty {
+            return false;
+        }
+        let (mut liberty, mut capture) = (false, false);
+        Board::loop_over_neighbours(x, y, self.size, |a, b| {
+            match self.point(a, b) {
+                Empty => liberty = true,
+                Stone(col, id) if col == colour => liberty = liberty || self.groups[self.find(id)].liberty_count() > 1,
+                Stone(_, id) => capture = capture || self.groups[self.find(id)].liberty_count() == 1
+            }
         });
+        if !liberty || capture {
+            return false;
+        }
+        self.put_stone(colour, x, y);
+        self.hash ^= zobrist_key(colour, x, y);
+        self.current_ko = (0, 0);
+        self.forget_history();
+        self.check_invariants("setup stone");
         true
     }
+
+    /// Checks that the stones array, the groups, their liberties and the
+    /// capture counters are consistent, aborting with a dump of the
+    /// position if they are not. Only active in debug builds.
+    #[cfg(not(ndebug))]
+    fn check_invariants(&self, context: &str) {
+        match self.find_inconsistency() {
+            Some(problem) => fail!("board inconsistency after {}: {}\n{}", context, problem, self.dump()),
+            None => {}
+        }
+    }
+
+    #[cfg(ndebug)]
+    #[inline(always)]
+    fn check_invariants(&self, _context: &str) {}
+
+    #[cfg(not(ndebug))]
+    fn find_inconsistency(&self) -> Option<String> {
+        if self.stones.len() != self.size * self.size {
+            return Some(format!("{} points stored for size {}", self.stones.len(), self.size));
+        }
+        // every stone belongs to an existing group containing it
+        for x in range(1u, self.size+1) {
+            for y in range(1u, self.size+1) {
+                match self.point(x, y) {
+                    Empty => {}
+                    Stone(col, id) => {
+                        let gid = self.find(id);
+                        if !self.groups.find(&gid).map_or(false, |g| g.stones.contains(x, y)) {
+                            return Some(format!("stone ({},{}) is not in its group {}", x, y, gid));
+                        }
+                        let mut problem = None;
+                        Board::loop_over_neighbours(x, y, self.size, |a, b| {
+                            single_match!(self.point(a, b) : Stone(ncol, nid) => {
+                                if ncol == col && self.find(nid) != gid {
+                                    problem = Some(format!("stones ({},{}) and ({},{}) are connected \
+                                                            but in different groups", x, y, a, b));
+                                }
+                            });
+                        });
+                        if problem.is_some() {
+                            return problem;
+                        }
+                    }
+                }
+            }
+        }
+        // every group is made of its stones and has exactly its liberties
+        for (gid, grp) in self.groups.iter() {
+            if grp.stones.is_empty() {
+                return Some(format!("group {} is empty", gid));
+            }
+            let mut liberties = TreeSet::new();
+            for (x, y) in grp.stones.iter() {
+                if x > self.size || y > self.size {
+                    return Some(format!("group {} has a stone outside of the board at ({},{})", gid, x, y));
+                }
+                match self.point(x, y) {
+                    Stone(_, id) if self.find(id) == gid => {}
+                    _ => return Some(format!("group {} claims ({},{}) which is not its stone", gid, x, y))
+                }
+                Board::loop_over_neighbours(x, y, self.size, |a, b| {
+                    if self.point(a, b) == Empty {
+                        liberties.insert((a, b));
+                    }
+                });
+            }
+            if liberties.is_empty() {
+                return Some(format!("group {} has no liberty", gid));
+            }
+            let recorded: Vec<(uint, uint)> = grp.get_liberties().collect();
+            let actual: Vec<(uint, uint)> = liberties.iter().map(|&p| p).collect();
+            if recorded != actual {
+                return Some(format!("group {} has liberties {} instead of {}", gid, recorded, actual));
+            }
+        }
+        // absorbed group ids all lead to an existing group
+        for (id, _) in self.links.iter() {
+            if !self.groups.contains_key(&self.find(id)) {
+                return Some(format!("stone id {} leads to no group", id));
+            }
+        }
+        // the history may only cover the end of the game (see
+        // clone_without_history), so counters can only be bounded
+        let (mut black_removed, mut white_removed) = (0u, 0u);
+        for mv in self.history.iter() {
+            for grp in mv.removed.iter() {
+                match (mv.player, mv.suicide) {
+                    (White, false) | (Black, true) => black_removed += grp.stone_count(),
+                    (Black, false) | (White, true) => white_removed += grp.stone_count()
+                }
+            }
+        }
+        if self.black_dead < black_removed || self.white_dead < white_removed {
+            return Some(format!("capture counters ({}, {}) below captures in history ({}, {})",
+                                self.black_dead, self.white_dead, black_removed, white_removed));
+        }
+        // the incremental hash matches the stones
+        let mut hash = 0u64;
+        for x in range(1u, self.size+1) {
+            for y in range(1u, self.size+1) {
+                single_match!(self.point(x, y) : Stone(col, _) => {
+                    hash ^= zobrist_key(col, x, y);
+                });
+            }
+        }
+        if hash != self.hash {
+            return Some(format!("hash is {:x} instead of {:x}", self.hash, hash));
+        }
+        None
+    }
+
+    #[cfg(not(ndebug))]
+    fn dump(&self) -> String {
+        let mut output = String::new();
+        for y in range(1u, self.size+1).rev() {
+            for x in range(1u, self.size+1) {
+                output.push_str(match self.point(x, y) {
+                    Stone(Black, _) => "X ",
+                    Stone(White, _) => "O ",
+                    Empty => ". "
+                });
+            }
+            output.push_str("\n");
+        }
+        output
+    }
+}
+
+/// Boards are equal when they hold the same position: the same stones,
+/// ko point and player to move. History, komi and rules are ignored.
+impl PartialEq for Board {
+    fn eq(&self, other: &Board) -> bool {
+        self.size == other.size && self.hash == other.hash
+            && self.current_ko == other.current_ko && self.to_move() == other.to_move()
+            && self.iter().zip(other.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl Eq for Board {}
+
+impl<S: hash::Writer> hash::Hash<S> for Board {
+    fn hash(&self, state: &mut S) {
+        // the zobrist hash already sums up the stones
+        self.size.hash(state);
+        self.hash.hash(state);
+        self.current_ko.hash(state);
+        (self.to_move() == Black).hash(state);
+    }
+}
+
+// Boards are handed to other tasks, this fails to compile if a field
+// ever makes them unsendable.
+#[allow(dead_code)]
+fn assert_board_is_send() {
+    fn is_send<T: Send>() {}
+    is_send::<Board>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Board, Black, White, PositionalSuperko};
+
+    // Black takes the white stone at B3 by playing C3, White may not
+    // retake at B3 at once
+    static ko_diagram : &'static str = "
+        . . . . .
+        . X O . .
+        X O . O .
+        . X O . .
+        . . . . .";
+
+    #[test]
+    fn ko_capture_pass_undo() {
+        let mut goban = Board::from_diagram(ko_diagram).unwrap();
+        let start = goban.hash();
+        assert_eq!(goban.get_current_ko(), None);
+        assert!(goban.play(Black, 3, 3).is_ok());
+        let captured = goban.hash();
+        assert!(captured != start);
+        assert_eq!(goban.get_current_ko(), Some((2, 3)));
+        goban.pass(White);
+        assert_eq!(goban.get_current_ko(), None);
+        assert_eq!(goban.hash(), captured);
+        assert!(goban.undo());
+        assert_eq!(goban.get_current_ko(), Some((2, 3)));
+        assert_eq!(goban.hash(), captured);
+        assert!(goban.undo());
+        assert_eq!(goban.get_current_ko(), None);
+        assert_eq!(goban.hash(), start);
+        assert_eq!(goban.at(2, 3), Some(White));
+    }
+
+    #[test]
+    fn ko_capture_pass_pass_undo_twice() {
+        let mut goban = Board::from_diagram(ko_diagram).unwrap();
+        let start = goban.hash();
+        assert!(goban.play(Black, 3, 3).is_ok());
+        let captured = goban.hash();
+        assert_eq!(goban.get_current_ko(), Some((2, 3)));
+        goban.pass(White);
+        assert_eq!(goban.get_current_ko(), None);
+        assert_eq!(goban.hash(), captured);
+        goban.pass(Black);
+        assert_eq!(goban.get_current_ko(), None);
+        assert_eq!(goban.hash(), captured);
+        // the ko was lifted by the first pass, not by the second one
+        assert!(goban.undo());
+        assert_eq!(goban.get_current_ko(), None);
+        assert_eq!(goban.hash(), captured);
+        assert!(goban.undo());
+        assert_eq!(goban.get_current_ko(), Some((2, 3)));
+        assert_eq!(goban.hash(), captured);
+        assert!(captured != start);
+    }
+
+    #[test]
+    fn ko_capture_undo_replay() {
+        let mut goban = Board::from_diagram(ko_diagram).unwrap();
+        let start = goban.hash();
+        assert!(goban.play(Black, 3, 3).is_ok());
+        let captured = goban.hash();
+        assert_eq!(goban.get_current_ko(), Some((2, 3)));
+        assert!(goban.undo());
+        assert_eq!(goban.get_current_ko(), None);
+        assert_eq!(goban.hash(), start);
+        assert_eq!(goban.at(2, 3), Some(White));
+        assert!(goban.play(Black, 3, 3).is_ok());
+        assert_eq!(goban.get_current_ko(), Some((2, 3)));
+        assert_eq!(goban.hash(), captured);
+        assert!(goban.play(White, 2, 3).is_err());
+    }
+
+    // White at B3 has a single liberty at C3
+    static suicide_diagram : &'static str = "
+        . X X .
+        X O . X
+        . X X .
+        . . . .";
+
+    #[test]
+    fn suicide_undo() {
+        let mut goban = Board::from_diagram(suicide_diagram).unwrap();
+        assert!(goban.play(White, 3, 3).is_err());
+        goban.set_allow_suicide(true);
+        let start = goban.hash();
+        assert!(goban.play(White, 3, 3).is_ok());
+        assert_eq!(goban.at(2, 3), None);
+        assert_eq!(goban.at(3, 3), None);
+        assert_eq!(goban.get_deads(), (0, 2));
+        assert!(goban.hash() != start);
+        assert!(goban.undo());
+        assert_eq!(goban.at(2, 3), Some(White));
+        assert_eq!(goban.at(3, 3), None);
+        assert_eq!(goban.get_deads(), (0, 0));
+        assert_eq!(goban.hash(), start);
+        assert_eq!(goban.get_groups()[goban.group_at(2, 3).unwrap()].liberty_count(), 1);
+    }
+
+    // Black takes the ko, both pass, then White retakes, which brings back
+    // the starting position
+    fn retake_after_passes(goban: &mut Board) -> bool {
+        assert!(goban.play(Black, 3, 3).is_ok());
+        goban.pass(White);
+        goban.pass(Black);
+        goban.play(White, 2, 3).is_ok()
+    }
+
+    #[test]
+    fn simple_ko_allows_repetition() {
+        let mut goban = Board::from_diagram(ko_diagram).unwrap();
+        let start = goban.hash();
+        assert!(retake_after_passes(&mut goban));
+        assert_eq!(goban.hash(), start);
+    }
+
+    #[test]
+    fn positional_superko_forbids_repetition() {
+        let mut goban = Board::from_diagram(ko_diagram).unwrap();
+        goban.set_ko_rule(PositionalSuperko);
+        assert!(!retake_after_passes(&mut goban));
+        assert_eq!(goban.at(2, 3), None);
+        assert_eq!(goban.get_current_ko(), None);
+    }
 }
@@ -0,0 +1,149 @@
+//! Time control of the game as set by the controller, and the thinking
+//! time it leaves for each move.
+//!
+//! The model is the canadian byo-yomi of GTP time_settings: a main time,
+//! then periods of a given time in which a given number of stones must be
+//! played. Other systems are mapped onto it.
+
+use std::cmp::{max, min};
+
+use libc::c_long;
+
+use board;
+
+extern {
+    // clock_t is a long on the supported platforms
+    fn clock() -> c_long;
+}
+
+// CLOCKS_PER_SEC, fixed by POSIX
+static clocks_per_sec : f64 = 1000000.0;
+
+/// CPU time used by the process so far, in seconds.
+pub fn cpu_time() -> f64 {
+    unsafe { clock() as f64 / clocks_per_sec }
+}
+
+/// Time control of the game, times in seconds.
+#[deriving(PartialEq, Show)]
+pub struct TimeSettings {
+    pub main_time: f64,
+    pub byoyomi_time: f64,
+    /// Stones to play in each byo-yomi period, 0 for absolute time (no
+    /// byo-yomi).
+    pub byoyomi_stones: uint
+}
+
+/// Time left to a player, as reported by GTP time_left.
+#[deriving(PartialEq, Show)]
+pub struct TimeLeft {
+    /// Seconds left in the main time, or in the current byo-yomi period.
+    pub time: f64,
+    /// Stones left to play in the current byo-yomi period, 0 while in the
+    /// main time.
+    pub stones: uint
+}
+
+/// Part of a byo-yomi period spent on a move, the rest being a safety
+/// margin against network lag.
+static byoyomi_share : f64 = 0.8;
+
+impl TimeSettings {
+    /// No time limit, the default until the controller sets one.
+    pub fn unlimited() -> TimeSettings {
+        // encoded as in GTP: byo-yomi time without byo-yomi stones
+        TimeSettings { main_time: 0.0, byoyomi_time: 1.0, byoyomi_stones: 0 }
+    }
+
+    /// Main time only.
+    pub fn absolute(main_time: f64) -> TimeSettings {
+        TimeSettings { main_time: main_time, byoyomi_time: 0.0, byoyomi_stones: 0 }
+    }
+
+    /// Japanese byo-yomi, periods of the given time for a single stone.
+    /// The extra periods are kept as a safety margin, each move is planned
+    /// to fit in one.
+    pub fn japanese(main_time: f64, period_time: f64, periods: uint) -> TimeSettings {
+        if periods == 0 {
+            TimeSettings::absolute(main_time)
+        } else {
+            TimeSettings { main_time: main_time, byoyomi_time: period_time, byoyomi_stones: 1 }
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.byoyomi_time > 0.0 && self.byoyomi_stones == 0
+    }
+
+    /// Thinking time for the next move in seconds, None without time limit.
+    /// The main time is spread over the moves the player can still expect
+    /// on a board of the given size, the byo-yomi time over its stones.
+    /// Without any report of the time left, the player is assumed to still
+    /// have all of its main time.
+    pub fn move_budget(&self, size: uint, move_number: uint, left: Option<TimeLeft>) -> Option<f64> {
+        if self.is_unlimited() {
+            return None;
+        }
+        let (main_time, byoyomi_time, byoyomi_stones) = match left {
+            None => (self.main_time, self.byoyomi_time, self.byoyomi_stones),
+            Some(TimeLeft { time, stones: 0 }) => (time, self.byoyomi_time, self.byoyomi_stones),
+            // already in byo-yomi, the stones reported for japanese byo-yomi
+            // are the periods left
+            Some(TimeLeft { time, stones }) => (0.0, time, min(stones, self.byoyomi_stones))
+        };
+        // moves of the player still to come, at least a few
+        let played = move_number / 2;
+        let expected = max(if size * size / 2 > played { size * size / 2 - played } else { 0 }, 10);
+        let main_part = main_time / expected as f64;
+        let byoyomi_part = if byoyomi_stones > 0 {
+            byoyomi_time * byoyomi_share / byoyomi_stones as f64
+        } else {
+            0.0
+        };
+        Some(if main_part > byoyomi_part { main_part } else { byoyomi_part })
+    }
+}
+
+/// The time settings of a game with the last time left reported for each
+/// player.
+#[deriving(PartialEq, Show)]
+pub struct GameClock {
+    pub settings: TimeSettings,
+    pub black_left: Option<TimeLeft>,
+    pub white_left: Option<TimeLeft>
+}
+
+impl GameClock {
+    /// No time limit and no report.
+    pub fn new() -> GameClock {
+        GameClock { settings: TimeSettings::unlimited(), black_left: None, white_left: None }
+    }
+
+    /// Changes the time settings, the reports about the previous ones are
+    /// dropped.
+    pub fn set_settings(&mut self, settings: TimeSettings) {
+        self.settings = settings;
+        self.black_left = None;
+        self.white_left = None;
+    }
+
+    pub fn time_left(&self, player: board::Colour) -> Option<TimeLeft> {
+        match player {
+            board::Black => self.black_left,
+            board::White => self.white_left
+        }
+    }
+
+    pub fn set_time_left(&mut self, player: board::Colour, left: TimeLeft) {
+        match player {
+            board::Black => self.black_left = Some(left),
+            board::White => self.white_left = Some(left)
+        }
+    }
+
+    /// Thinking time for the next move of the player, see
+    /// TimeSettings::move_budget.
+    pub fn move_budget(&self, player: board::Colour, size: uint, move_number: uint) -> Option<f64> {
+        self.settings.move_budget(size, move_number, self.time_left(player))
+    }
+}
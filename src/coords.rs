@@ -0,0 +1,105 @@
+//! Conversions between the board representation of points, colours and
+//! moves and their GTP counterparts. All casts between board coordinates
+//! and gtprust's u8 coordinates happen here, and every conversion that
+//! can fail returns an Option rather than panicking.
+
+use std::ascii::StrAsciiExt;
+
+use gtprust::api;
+
+use board;
+
+/// GTP vertex of a board point, None if the point cannot be represented.
+pub fn to_vertex(x: uint, y: uint) -> Option<api::Vertex> {
+    if x == 0 || y == 0 || x > board::board_maxsize || y > board::board_maxsize {
+        None
+    } else {
+        api::Vertex::from_coords(x as u8, y as u8)
+    }
+}
+
+/// Board point of a GTP vertex.
+pub fn from_vertex(vertex: api::Vertex) -> (uint, uint) {
+    let (x, y) = vertex.to_coords();
+    (x as uint, y as uint)
+}
+
+pub fn to_api_colour(colour: board::Colour) -> api::Colour {
+    match colour { board::Black => api::Black, board::White => api::White }
+}
+
+pub fn from_api_colour(colour: api::Colour) -> board::Colour {
+    match colour { api::Black => board::Black, api::White => board::White }
+}
+
+/// GTP move corresponding to a board move.
+pub fn to_api_move(move: board::Vertex) -> Option<api::Move> {
+    match move {
+        board::Put(x, y) => to_vertex(x, y).map(|v| api::Stone(v)),
+        board::Pass => Some(api::Pass)
+    }
+}
+
+/// Board move corresponding to a GTP move, None for a resignation.
+pub fn from_api_move(move: api::Move) -> Option<board::Vertex> {
+    match move {
+        api::Stone(vrtx) => {
+            let (x, y) = from_vertex(vrtx);
+            Some(board::Put(x, y))
+        }
+        api::Pass => Some(board::Pass),
+        api::Resign => None
+    }
+}
+
+/// Parses a GTP colour argument.
+pub fn parse_colour(s: &str) -> Option<api::Colour> {
+    match s.to_ascii_lower().as_slice() {
+        "b" | "black" => Some(api::Black),
+        "w" | "white" => Some(api::White),
+        _ => None
+    }
+}
+
+/// Parses a GTP vertex such as "D4" into a board point.
+pub fn parse_vertex(s: &str) -> Option<(uint, uint)> {
+    let s = s.to_ascii_upper();
+    let mut chars = s.as_slice().chars();
+    let letter = match chars.next() {
+        Some(c) if c >= 'A' && c <= 'Z' && c != 'I' => c,
+        _ => return None
+    };
+    // the letter I is skipped to avoid confusion with J
+    let x = if letter > 'I' { letter as uint - 'A' as uint } else { letter as uint - 'A' as uint + 1 };
+    match from_str::<uint>(s.as_slice().slice_from(1)) {
+        Some(y) if to_vertex(x, y).is_some() => Some((x, y)),
+        _ => None
+    }
+}
+
+/// Parses a GTP move argument: a vertex, pass or resign.
+pub fn parse_move(s: &str) -> Option<api::Move> {
+    match s.to_ascii_lower().as_slice() {
+        "pass" => Some(api::Pass),
+        "resign" => Some(api::Resign),
+        _ => parse_vertex(s).and_then(|(x, y)| to_vertex(x, y)).map(|v| api::Stone(v))
+    }
+}
+
+/// Formats a board point as a GTP vertex.
+pub fn format_vertex(x: uint, y: uint) -> String {
+    let letter = if x >= 9 { 'A' as uint + x } else { 'A' as uint + x - 1 };
+    format!("{}{}", (letter as u8) as char, y)
+}
+
+/// Formats a GTP move.
+pub fn format_move(move: api::Move) -> String {
+    match move {
+        api::Pass => String::from_str("PASS"),
+        api::Resign => String::from_str("resign"),
+        api::Stone(vrtx) => {
+            let (x, y) = from_vertex(vrtx);
+            format_vertex(x, y)
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! A small DSL describing board rule cases with diagrams: a position, a
+//! move, and the expected position afterwards, plus optional expected
+//! captures and ko point.
+//!
+//! ```text
+//! case capture on the edge
+//! . X .
+//! X O X
+//! . . .
+//! play B B1
+//! . X .
+//! X . X
+//! . X .
+//! captures 1
+//! ko none
+//! end
+//!
+//! case suicide is illegal
+//! . X .
+//! X . X
+//! . X .
+//! play W B2
+//! illegal
+//! end
+//! ```
+//!
+//! Diagrams are square, top row first, 'X' for black, 'O' for white and
+//! '.' for empty points; vertices use GTP notation.
+
+use std::io::File;
+
+use board;
+use coords::parse_vertex;
+
+/// A single rule case.
+pub struct Case {
+    pub name: String,
+    pub before: Vec<String>,
+    pub player: board::Colour,
+    pub at: (uint, uint),
+    /// None if the move is expected to be illegal.
+    pub after: Option<Vec<String>>,
+    pub captures: Option<uint>,
+    pub ko: Option<Option<(uint, uint)>>
+}
+
+fn parse_row(row: &str) -> Result<Vec<Option<board::Colour>>, String> {
+    row.chars().filter(|c| !c.is_whitespace()).map(|c| match c {
+        '.' | '+' => Ok(None),
+        'X' => Ok(Some(board::Black)),
+        'O' => Ok(Some(board::White)),
+        _ => Err(format!("invalid character '{}' in diagram", c))
+    }).collect()
+}
+
+/// Builds a board from diagram rows, top row first.
+pub fn board_from_diagram(rows: &[String]) -> Result<board::Board, String> {
+    board::Board::from_diagram(rows.connect("\n").as_slice())
+}
+
+/// Renders the board as diagram rows, top row first.
+pub fn diagram_of(goban: &board::Board) -> Vec<String> {
+    let size = goban.get_size();
+    range(0, size).map(|i| {
+        let y = size - i;
+        let row: Vec<&str> = range(1, size + 1).map(|x| match goban.at(x, y) {
+            Some(board::Black) => "X",
+            Some(board::White) => "O",
+            None => "."
+        }).collect();
+        row.connect(" ")
+    }).collect()
+}
+
+fn same_diagram(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).all(|(ra, rb)| {
+        parse_row(ra.as_slice()) == parse_row(rb.as_slice())
+    })
+}
+
+/// Parses all cases of a case file.
+pub fn parse_cases(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+    let mut lines = text.lines().map(|l| l.trim()).enumerate()
+                        .filter(|&(_, l)| !l.is_empty() && !l.starts_with("#"));
+    loop {
+        let (n, header) = match lines.next() {
+            Some(l) => l,
+            None => return Ok(cases)
+        };
+        if !header.starts_with("case") {
+            return Err(format!("line {}: expected \"case\"", n + 1));
+        }
+        let mut case = Case {
+            name: String::from_str(header.slice_from(4).trim()),
+            before: Vec::new(),
+            player: board::Black,
+            at: (0, 0),
+            after: None,
+            captures: None,
+            ko: None
+        };
+        let mut after = Vec::new();
+        let mut played = false;
+        loop {
+            let (n, line) = match lines.next() {
+                Some(l) => l,
+                None => return Err(format!("case \"{}\" is missing \"end\"", case.name))
+            };
+            let words: Vec<&str> = line.words().collect();
+            let error = Err(format!("line {}: invalid \"{}\"", n + 1, line));
+            match words.as_slice() {
+                ["end"] => break,
+                ["play", colour, vertex] if !played => {
+                    match (colour, parse_vertex(vertex)) {
+                        ("B", Some(at)) | ("X", Some(at)) => { case.player = board::Black; case.at = at; }
+                        ("W", Some(at)) | ("O", Some(at)) => { case.player = board::White; case.at = at; }
+                        _ => return error
+                    }
+                    played = true;
+                }
+                ["illegal"] if played => {}
+                ["captures", count] if played => match from_str::<uint>(count) {
+                    Some(c) => case.captures = Some(c),
+                    None => return error
+                },
+                ["ko", "none"] if played => case.ko = Some(None),
+                ["ko", vertex] if played => match parse_vertex(vertex) {
+                    Some(v) => case.ko = Some(Some(v)),
+                    None => return error
+                },
+                _ if !played => case.before.push(String::from_str(line)),
+                _ => after.push(String::from_str(line))
+            }
+        }
+        if !after.is_empty() {
+            case.after = Some(after);
+        }
+        cases.push(case);
+    }
+}
+
+/// Checks a single case, describing the first mismatch.
+pub fn run_case(case: &Case) -> Result<(), String> {
+    let mut goban = try!(board_from_diagram(case.before.as_slice()));
+    let (black_dead, white_dead) = goban.get_deads();
+    let (x, y) = case.at;
+    let after = match (goban.play(case.player, x, y), &case.after) {
+        (Err(_), &None) => return Ok(()),
+        (Ok(()), &None) => return Err(String::from_str("move was accepted but should be illegal")),
+        (Err(reason), &Some(_)) => return Err(format!("move was rejected ({}) but should be legal", reason)),
+        (Ok(()), &Some(ref after)) => after
+    };
+    let result = diagram_of(&goban);
+    if !same_diagram(result.as_slice(), after.as_slice()) {
+        return Err(format!("unexpected position:\n{}", result.connect("\n")));
+    }
+    let (new_black_dead, new_white_dead) = goban.get_deads();
+    let captured = (new_black_dead - black_dead) + (new_white_dead - white_dead);
+    match case.captures {
+        Some(c) if c != captured => return Err(format!("captured {} stones instead of {}", captured, c)),
+        _ => {}
+    }
+    match case.ko {
+        Some(ko) if ko != goban.get_current_ko() => {
+            return Err(format!("ko is {} instead of {}", goban.get_current_ko(), ko));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Runs all cases of a file, printing failures on the output and
+/// returning the number of passed and failed cases.
+pub fn run_file<W: Writer>(path: &Path, output: &mut W) -> Result<(uint, uint), String> {
+    let contents = match File::open(path).read_to_string() {
+        Ok(c) => c,
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+    };
+    let cases = match parse_cases(contents.as_slice()) {
+        Ok(c) => c,
+        Err(e) => return Err(format!("{}: {}", path.display(), e))
+    };
+    let (mut passed, mut failed) = (0u, 0u);
+    for case in cases.iter() {
+        match run_case(case) {
+            Ok(()) => passed += 1,
+            Err(e) => {
+                failed += 1;
+                let _ = writeln!(output, "{}: case \"{}\" FAILED: {}", path.display(), case.name, e);
+            }
+        }
+    }
+    Ok((passed, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::run_file;
+
+    #[test]
+    fn case_files() {
+        for name in ["capture.txt", "ko.txt", "suicide.txt"].iter() {
+            let path = Path::new("tests/diagrams").join(*name);
+            match run_file(&path, &mut io::stdout()) {
+                Ok((passed, failed)) => {
+                    assert!(passed > 0, "{}: no case", path.display());
+                    assert!(failed == 0, "{}: {} cases failed", path.display(), failed);
+                }
+                Err(e) => fail!("{}", e)
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Differential rules testing: plays random games while mirroring every
+//! move to a reference engine over GTP, comparing legality verdicts and
+//! final positions to flush out rule discrepancies.
+
+use std::collections::TreeSet;
+use std::io::IoResult;
+use std::rand::{task_rng, Rng};
+
+use board;
+use coords;
+use proxy;
+
+static test_boardsize : uint = 9;
+static max_moves : uint = 200;
+// random candidates tried before a player passes
+static max_attempts : uint = 100;
+
+fn gtp_colour(colour: board::Colour) -> &'static str {
+    match colour { board::Black => "black", board::White => "white" }
+}
+
+fn parse_stones(response: &str) -> TreeSet<(uint, uint)> {
+    response.words().skip(1).filter_map(|v| coords::parse_vertex(v)).collect()
+}
+
+fn our_stones(goban: &board::Board, colour: board::Colour) -> TreeSet<(uint, uint)> {
+    goban.iter().filter(|&(_, _, stone)| stone == Some(colour)).map(|(x, y, _)| (x, y)).collect()
+}
+
+/// Plays the given number of random games against the reference engine,
+/// reporting every discrepancy on the output. Returns the number of
+/// games that showed a discrepancy.
+pub fn run<W: Writer>(games: uint, program: &str, args: &[String], output: &mut W) -> IoResult<uint> {
+    let mut engine = try!(proxy::Engine::spawn(program, args));
+    let mut rng = task_rng();
+    let mut faulty = 0u;
+    for game in range(0, games) {
+        try!(engine.send(format!("boardsize {}", test_boardsize).as_slice()));
+        try!(engine.send("clear_board"));
+        let mut goban = board::Board::new();
+        goban.resize(test_boardsize);
+        let mut record = Vec::new();
+        let mut player = board::Black;
+        let mut discrepancy = None;
+        for _ in range(0, max_moves) {
+            let mut played = false;
+            for _ in range(0, max_attempts) {
+                let (x, y) = (rng.gen_range(1u, test_boardsize+1), rng.gen_range(1u, test_boardsize+1));
+                let vertex = coords::format_vertex(x, y);
+                let ours = goban.play(player, x, y).is_ok();
+                let response = try!(engine.send(format!("play {} {}", gtp_colour(player), vertex).as_slice()));
+                let theirs = response.as_slice().starts_with("=");
+                if ours != theirs {
+                    discrepancy = Some(format!("{} {}: we say {}, engine says {}", gtp_colour(player), vertex,
+                                               if ours { "legal" } else { "illegal" },
+                                               if theirs { "legal" } else { "illegal" }));
+                    break;
+                }
+                if ours {
+                    record.push(format!("{} {}", player, board::Put(x, y)));
+                    played = true;
+                    break;
+                }
+            }
+            if discrepancy.is_some() {
+                break;
+            }
+            if !played {
+                goban.pass(player);
+                try!(engine.send(format!("play {} pass", gtp_colour(player)).as_slice()));
+                record.push(format!("{} {}", player, board::Pass));
+            }
+            player = match player { board::Black => board::White, board::White => board::Black };
+        }
+        if discrepancy.is_none() {
+            for &colour in [board::Black, board::White].iter() {
+                let response = try!(engine.send(format!("list_stones {}", gtp_colour(colour)).as_slice()));
+                if parse_stones(response.as_slice()) != our_stones(&goban, colour) {
+                    discrepancy = Some(format!("final {} stones differ", gtp_colour(colour)));
+                }
+            }
+        }
+        match discrepancy {
+            Some(problem) => {
+                faulty += 1;
+                try!(writeln!(output, "game {}: {}", game + 1, problem));
+                try!(writeln!(output, "moves: {}", record.connect(", ")));
+            }
+            None => {}
+        }
+    }
+    try!(engine.send("quit"));
+    try!(engine.wait());
+    Ok(faulty)
+}
@@ -0,0 +1,153 @@
+//! Game trees: a starting position and all the variations explored from
+//! it, every node carrying a comment. The tree keeps a board in the
+//! position of its current node, navigation plays or undoes the moves.
+
+use board;
+
+struct Node {
+    // None for the root
+    move: Option<(board::Colour, board::Vertex)>,
+    parent: Option<uint>,
+    children: Vec<uint>,
+    comment: String
+}
+
+impl Node {
+    fn new(move: Option<(board::Colour, board::Vertex)>, parent: Option<uint>) -> Node {
+        Node { move: move, parent: parent, children: Vec::new(), comment: String::new() }
+    }
+}
+
+pub struct GameTree {
+    nodes: Vec<Node>,
+    current: uint,
+    goban: board::Board
+}
+
+impl GameTree {
+    /// A tree rooted at the current position of the board.
+    pub fn new(goban: board::Board) -> GameTree {
+        GameTree {
+            nodes: vec!(Node::new(None, None)),
+            current: 0,
+            goban: goban
+        }
+    }
+
+    /// The position of the current node.
+    pub fn board<'a>(&'a self) -> &'a board::Board {
+        &self.goban
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.current == 0
+    }
+
+    /// The move leading to the current node, None at the root.
+    pub fn last_move(&self) -> Option<(board::Colour, board::Vertex)> {
+        self.nodes[self.current].move
+    }
+
+    /// Moves of the variations from the current node, the first one being
+    /// the main line.
+    pub fn variations(&self) -> Vec<(board::Colour, board::Vertex)> {
+        self.nodes[self.current].children.iter().map(|&c| self.nodes[c].move.unwrap()).collect()
+    }
+
+    pub fn get_comment<'a>(&'a self) -> &'a str {
+        self.nodes[self.current].comment.as_slice()
+    }
+
+    pub fn set_comment(&mut self, comment: &str) {
+        self.nodes.get_mut(self.current).comment = String::from_str(comment);
+    }
+
+    // applies the move on the board, false if it is illegal
+    fn apply(&mut self, colour: board::Colour, move: board::Vertex) -> bool {
+        self.goban.play_move(colour, move).is_ok()
+    }
+
+    /// Plays a move from the current node and goes to the resulting node.
+    /// If no variation starts with this move, a new one is added after the
+    /// existing ones. Returns false if the move is illegal.
+    pub fn play(&mut self, colour: board::Colour, move: board::Vertex) -> bool {
+        if !self.apply(colour, move) {
+            return false;
+        }
+        let existing = self.nodes[self.current].children.iter()
+                           .find(|&&c| self.nodes[c].move == Some((colour, move)))
+                           .map(|&c| c);
+        self.current = match existing {
+            Some(child) => child,
+            None => {
+                let child = self.nodes.len();
+                self.nodes.push(Node::new(Some((colour, move)), Some(self.current)));
+                self.nodes.get_mut(self.current).children.push(child);
+                child
+            }
+        };
+        true
+    }
+
+    /// Goes to the parent node. Returns false at the root.
+    pub fn back(&mut self) -> bool {
+        match self.nodes[self.current].parent {
+            Some(parent) => {
+                self.goban.undo();
+                self.current = parent;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Goes to the first node of the given variation, 0 being the main
+    /// line. Returns false if there is no such variation.
+    pub fn forward(&mut self, variation: uint) -> bool {
+        let child = match self.nodes[self.current].children.as_slice().get(variation) {
+            Some(&c) => c,
+            None => return false
+        };
+        let (colour, move) = self.nodes[child].move.unwrap();
+        // the move was legal when the node was added, from the same history
+        let legal = self.apply(colour, move);
+        debug_assert!(legal);
+        self.current = child;
+        true
+    }
+
+    /// Goes back to the starting position.
+    pub fn to_root(&mut self) {
+        while self.back() {}
+    }
+
+    /// Removes the given variation of the current node, and all the nodes
+    /// after it. Returns false if there is no such variation.
+    pub fn remove_variation(&mut self, variation: uint) -> bool {
+        // the nodes are left unreachable rather than renumbering the tree
+        let children = &mut self.nodes.get_mut(self.current).children;
+        if variation < children.len() {
+            children.remove(variation);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The moves of the main line, following the first variation from the
+    /// root.
+    pub fn main_line(&self) -> Vec<(board::Colour, board::Vertex)> {
+        let mut moves = Vec::new();
+        let mut node = 0u;
+        loop {
+            match self.nodes[node].children.as_slice().head() {
+                Some(&child) => {
+                    moves.push(self.nodes[child].move.unwrap());
+                    node = child;
+                }
+                None => break
+            }
+        }
+        moves
+    }
+}
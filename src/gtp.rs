@@ -1,36 +1,740 @@
+use std::io;
+use std::io::File;
+use std::rand::{task_rng, Rng, SeedableRng, StdRng};
+use std::sync::{Arc, Mutex};
+
 use gtprust::api;
+use gtprust::api::GoBot;
+use time;
 
 use board;
+use clock;
+use coords;
+use persist;
 use randomplay;
+use sgf;
 use statics;
+use status;
 
 pub struct ClockGoBot {
     goban: board::Board,
-    komi: f32,
+    monitor: Option<Arc<Mutex<board::Snapshot>>>,
+    json_output: bool,
+    autosave: Option<Path>,
+    // both players passed but the game goes on, dead stones are captured
+    cleanup: bool,
+    clock: clock::GameClock,
+    // winrate below which the engine resigns, None to never resign
+    resign_threshold: Option<f32>,
+    // consecutive genmoves with a winrate below the threshold, for Black
+    // and White
+    black_hopeless_moves: uint,
+    white_hopeless_moves: uint,
+    // last winrate estimated, with what identifies its position: board
+    // size, komi, hash and player
+    last_winrate: Option<(uint, f32, u64, board::Colour, f32)>,
+    // source of all the randomness of the engine, see set_seed
+    rng: StdRng
+}
+
+// commands handled through gtp_custom_command, play included so that
+// its errors can tell why a move is illegal
+static custom_commands : &'static [&'static str] = &["cg_list_groups", "time_settings", "time_left",
+                                                        "kgs-time_settings", "final_status_list",
+                                                        "fixed_handicap", "place_free_handicap",
+                                                        "set_free_handicap", "loadsgf", "printsgf",
+                                                        "reg_genmove", "kgs-genmove_cleanup",
+                                                        "cg_showboard", "cputime", "cg_move_times",
+                                                        "cg_score_estimate", "cg_winrate", "cg_undo_n", "cg_hash",
+                                                        "cg_legal_moves", "cg_seed", "cg_liberties", "cg_rules",
+                                                        "play"];
+
+/// Playouts run to estimate the status of the groups for final_status_list.
+static final_status_playouts : uint = 100;
+/// Playouts run for cg_score_estimate.
+static score_estimate_playouts : uint = 100;
+/// Playouts run to estimate the winrate before each move, when resigning
+/// is allowed, and for cg_winrate. Fewer are run when short of time.
+pub static winrate_playouts : uint = 50;
+/// Fewest playouts run to estimate the winrate, however short of time.
+static min_winrate_playouts : uint = 10;
+/// Consecutive moves with a winrate below the threshold before resigning.
+static resign_moves : uint = 3;
+
+/// Textual dump of all the groups of a board, with their stones and liberties.
+pub fn list_groups(goban: &board::Board) -> String {
+    let mut output = String::from_str("Groups:\n");
+    for (gid, grp) in goban.get_groups().iter() {
+        output = output.append(format!("{} :: {}\n", gid, grp).as_slice());
+    }
+    output
+}
+
+/// ASCII diagram of the board, with the coordinates, the last move between
+/// parentheses, the ko point and the prisoners taken by each player.
+pub fn show_board(goban: &board::Board) -> String {
+    let size = goban.get_size();
+    let stars = goban.star_points();
+    let last = match goban.last_move() {
+        Some((_, board::Put(x, y))) => Some((x, y)),
+        _ => None
+    };
+    // column letters, skipping I as GTP does
+    let letters: Vec<String> = range(1, size + 1).map(|x| {
+        String::from_str(coords::format_vertex(x, 1).as_slice().slice_to(1))
+    }).collect();
+    let header = format!("   {}", letters.connect(" "));
+    let mut output = header.clone();
+    for y in range(1, size + 1).rev() {
+        let mut row = format!("\n{:>2}", y);
+        for x in range(1, size + 1) {
+            row.push_char(if last == Some((x, y)) {
+                '('
+            } else if x > 1 && last == Some((x - 1, y)) {
+                ')'
+            } else {
+                ' '
+            });
+            row.push_char(match goban.at(x, y) {
+                Some(board::Black) => 'X',
+                Some(board::White) => 'O',
+                None if stars.contains(&(x, y)) => '+',
+                None => '.'
+            });
+        }
+        row.push_char(if last == Some((size, y)) { ')' } else { ' ' });
+        output.push_str(format!("{}{}", row, y).as_slice());
+    }
+    output.push_str(format!("\n{}", header).as_slice());
+    let (black_dead, white_dead) = goban.get_deads();
+    output.push_str(format!("\nBlack (X) prisoners: {}\nWhite (O) prisoners: {}", white_dead, black_dead).as_slice());
+    let ko = match goban.get_current_ko() {
+        Some((x, y)) => coords::format_vertex(x, y),
+        None => String::from_str("none")
+    };
+    output.push_str(format!("\nKo: {}", ko).as_slice());
+    output
+}
+
+// formats a list of points as GTP vertices separated by spaces
+fn points_to_vertices(points: &[(uint, uint)]) -> String {
+    let vertices: Vec<String> = points.iter().map(|&(x, y)| coords::format_vertex(x, y)).collect();
+    vertices.connect(" ")
+}
+
+// formats a list of points as a JSON array of [x, y] pairs
+fn points_to_json<I: Iterator<(uint, uint)>>(points: I) -> String {
+    let coords: Vec<String> = points.map(|(x, y)| format!("[{},{}]", x, y)).collect();
+    format!("[{}]", coords.connect(","))
+}
+
+/// Same as list_groups, but formatted as a JSON object.
+pub fn list_groups_json(goban: &board::Board) -> String {
+    let groups: Vec<String> = goban.get_groups().iter().map(|(gid, grp)| {
+        format!("{{\"id\":{},\"stones\":{},\"liberties\":{}}}", gid,
+                points_to_json(grp.get_stones()),
+                points_to_json(grp.get_liberties()))
+    }).collect();
+    format!("{{\"groups\":[{}]}}", groups.connect(","))
+}
+
+/// Answer of cg_score_estimate [ownership]: the expected score, followed
+/// by the ownership of every point if asked, top row first.
+pub fn score_estimate<R: Rng>(goban: &board::Board, args: &str, json: bool, rng: &mut R) -> (bool, String) {
+    let with_ownership = match args.trim() {
+        "" => false,
+        "ownership" => true,
+        _ => return (false, String::from_str("syntax error"))
+    };
+    let size = goban.get_size();
+    let estimate = status::score_estimate(goban, score_estimate_playouts, rng);
+    // rows of the ownership, top row first
+    let rows: Vec<Vec<String>> = range(1, size + 1).rev().map(|y| {
+        range(1, size + 1).map(|x| format!("{:.2}", estimate.ownership[(x-1) * size + y-1])).collect()
+    }).collect();
+    let answer = if json {
+        let mut object = format!("{{\"score\":{:.1}", estimate.score);
+        if with_ownership {
+            let rows: Vec<String> = rows.iter().map(|r| format!("[{}]", r.connect(","))).collect();
+            object.push_str(format!(",\"ownership\":[{}]", rows.connect(",")).as_slice());
+        }
+        object.push_char('}');
+        object
+    } else {
+        let mut text = if estimate.score >= 0.0 {
+            format!("B+{:.1}", estimate.score)
+        } else {
+            format!("W+{:.1}", -estimate.score)
+        };
+        if with_ownership {
+            for row in rows.iter() {
+                text.push_str(format!("\n{}", row.connect(" ")).as_slice());
+            }
+        }
+        text
+    };
+    (true, answer)
+}
+
+/// Answer of cg_legal_moves colour: the points where the player can play.
+pub fn legal_moves(goban: &board::Board, args: &str, json: bool) -> (bool, String) {
+    let colour = match coords::parse_colour(args.trim()) {
+        Some(c) => coords::from_api_colour(c),
+        None => return (false, String::from_str("syntax error"))
+    };
+    let moves = goban.legal_moves(colour);
+    if json {
+        (true, points_to_json(moves.iter().map(|&p| p)))
+    } else {
+        (true, points_to_vertices(moves.as_slice()))
+    }
+}
+
+/// Answer of cg_liberties vertex: the liberties and the stones of the
+/// group there.
+pub fn liberties(goban: &board::Board, args: &str, json: bool) -> (bool, String) {
+    let size = goban.get_size();
+    let gid = match coords::parse_vertex(args.trim()) {
+        Some((x, y)) if x >= 1 && y >= 1 && x <= size && y <= size => match goban.group_at(x, y) {
+            Some(gid) => gid,
+            None => return (false, String::from_str("empty vertex"))
+        },
+        _ => return (false, String::from_str("invalid vertex"))
+    };
+    let grp = &goban.get_groups()[gid];
+    if json {
+        (true, format!("{{\"liberties\":{},\"stones\":{}}}",
+                       points_to_json(grp.get_liberties()), points_to_json(grp.get_stones())))
+    } else {
+        let liberties: Vec<(uint, uint)> = grp.get_liberties().collect();
+        let stones: Vec<(uint, uint)> = grp.get_stones().collect();
+        (true, format!("liberties: {}\nstones: {}", points_to_vertices(liberties.as_slice()),
+                       points_to_vertices(stones.as_slice())))
+    }
+}
+
+/// Answer of cg_hash: the Zobrist hash of the position and its canonical
+/// hash, in hexadecimal.
+pub fn hash(goban: &board::Board, json: bool) -> String {
+    let (hash, canonical) = (goban.hash(), goban.canonical_hash());
+    if json {
+        format!("{{\"hash\":\"{:016x}\",\"canonical\":\"{:016x}\"}}", hash, canonical)
+    } else {
+        format!("{:016x} {:016x}", hash, canonical)
+    }
+}
+
+/// Answer of cg_winrate, given the winrate of the player to move.
+pub fn winrate(player: board::Colour, winrate: f32, json: bool) -> String {
+    if json {
+        format!("{{\"player\":\"{}\",\"winrate\":{:.3}}}", player, winrate)
+    } else {
+        format!("{} {:.3}", player, winrate)
+    }
 }
 
 impl ClockGoBot {
     pub fn new() -> ClockGoBot {
+        let mut goban = board::Board::new();
+        goban.set_komi(5.5f32);
         ClockGoBot {
-            goban: board::Board::new(),
-            komi: 5.5f32
+            goban: goban,
+            monitor: None,
+            json_output: false,
+            autosave: None,
+            cleanup: false,
+            clock: clock::GameClock::new(),
+            resign_threshold: None,
+            black_hopeless_moves: 0,
+            white_hopeless_moves: 0,
+            last_winrate: None,
+            rng: SeedableRng::from_seed([task_rng().gen::<uint>()].as_slice())
+        }
+    }
+
+    /// Shares the current position with a read-only monitor session,
+    /// it will be kept up to date after every change of the board.
+    pub fn set_monitor(&mut self, shared: Arc<Mutex<board::Snapshot>>) {
+        self.monitor = Some(shared);
+        self.board_changed();
+    }
+
+    /// Saves the game to the given file after every change of the board.
+    /// If the file already exists, the game it contains is resumed first.
+    pub fn set_autosave(&mut self, path: Path) -> Result<(), String> {
+        if path.exists() {
+            let (game, clock) = try!(persist::load(&path));
+            self.clock = clock;
+            match game.replay_on(&mut self.goban) {
+                Ok(()) => {}
+                Err(e) => return Err(format!("{}: {}", path.display(), e))
+            }
+            self.game_replaced();
         }
+        self.autosave = Some(path);
+        self.board_changed();
+        Ok(())
+    }
+
+    // keeps the monitor and the save file in sync with the board
+    fn board_changed(&self) {
+        match self.monitor {
+            Some(ref shared) => {
+                let snapshot = self.goban.snapshot();
+                *shared.lock() = snapshot;
+            }
+            None => {}
+        }
+        match self.autosave {
+            Some(ref path) => {
+                // failing to save must not interrupt the game
+                let _ = persist::save(path, &self.goban, &self.clock);
+            }
+            None => {}
+        }
+    }
+
+    // the board was cleared or replaced, what was learnt about the previous
+    // game no longer applies
+    fn game_replaced(&mut self) {
+        self.cleanup = false;
+        self.black_hopeless_moves = 0;
+        self.white_hopeless_moves = 0;
+        self.last_winrate = None;
+    }
+
+    // moves were taken back
+    fn moves_undone(&mut self) {
+        // taking back one of the final passes resumes the game
+        self.cleanup = self.cleanup && self.goban.both_passed();
+        // the estimates which led to them no longer count
+        self.black_hopeless_moves = 0;
+        self.white_hopeless_moves = 0;
+    }
+
+    // the game is over: archive it
+    fn game_over(&mut self) {
+        match self.autosave.take() {
+            Some(path) => {
+                let _ = persist::archive(&path, &self.goban, &self.clock, &self.goban.final_result());
+                // a new game will be saved in the same place
+                self.autosave = Some(path);
+            }
+            None => {}
+        }
+    }
+
+    /// Limits the number of moves which can be undone, see
+    /// Board::set_history_limit, None for no limit.
+    pub fn set_history_limit(&mut self, limit: Option<uint>) {
+        self.goban.set_history_limit(limit);
+    }
+
+    /// Makes the cg_* analysis commands answer in JSON rather than text.
+    pub fn set_json_output(&mut self, json: bool) {
+        self.json_output = json;
+    }
+
+    /// Seeds the random number generator of the engine, so that its moves
+    /// and estimates can be reproduced. It is seeded randomly otherwise.
+    pub fn set_seed(&mut self, seed: uint) {
+        self.rng = SeedableRng::from_seed([seed].as_slice());
+        // the cached estimate would not be reproduced
+        self.last_winrate = None;
+    }
+
+    /// Changes the rules from a list of words separated by spaces or
+    /// commas: "simple", "positional" or "situational" for the ko rule,
+    /// "suicide" or "nosuicide" to allow multi-stone suicide or not. The
+    /// rules not named are kept. Returns false, changing nothing, on an
+    /// unknown word.
+    pub fn set_rules(&mut self, rules: &str) -> bool {
+        let mut ko_rule = self.goban.get_ko_rule();
+        let mut suicide = self.goban.get_allow_suicide();
+        for word in rules.split(|c: char| c == ',' || c.is_whitespace()).filter(|w| !w.is_empty()) {
+            match word {
+                "simple" => ko_rule = board::SimpleKo,
+                "positional" => ko_rule = board::PositionalSuperko,
+                "situational" => ko_rule = board::SituationalSuperko,
+                "suicide" => suicide = true,
+                "nosuicide" => suicide = false,
+                _ => return false
+            }
+        }
+        self.goban.set_ko_rule(ko_rule);
+        self.goban.set_allow_suicide(suicide);
+        true
+    }
+
+    // the rules in force, in the words of set_rules
+    fn rules(&self) -> String {
+        let ko_rule = match self.goban.get_ko_rule() {
+            board::SimpleKo => "simple",
+            board::PositionalSuperko => "positional",
+            board::SituationalSuperko => "situational"
+        };
+        format!("{} {}", ko_rule, if self.goban.get_allow_suicide() { "suicide" } else { "nosuicide" })
+    }
+
+    /// Makes the engine resign once its winrate has stayed below the
+    /// threshold for a few moves, None to play every game to the end.
+    pub fn set_resign_threshold(&mut self, threshold: Option<f32>) {
+        self.resign_threshold = threshold;
+        self.black_hopeless_moves = 0;
+        self.white_hopeless_moves = 0;
+    }
+
+    // winrate of the player in the current position, the last estimate is
+    // reused if it was made for the same position; the playouts fit in the
+    // thinking time of the player
+    fn winrate(&mut self, player: board::Colour) -> f32 {
+        // the hash is the same for all empty boards, and ignores the komi
+        let key = (self.goban.get_size(), self.goban.get_komi(), self.goban.hash(), player);
+        match self.last_winrate {
+            Some((size, komi, hash, p, winrate)) if (size, komi, hash, p) == key => return winrate,
+            _ => {}
+        }
+        let playouts = status::playouts_within(self.move_budget(player), winrate_playouts, min_winrate_playouts);
+        let winrate = status::winrate(&self.goban, player, playouts, &mut self.rng);
+        let (size, komi, hash, _) = key;
+        self.last_winrate = Some((size, komi, hash, player, winrate));
+        winrate
+    }
+
+    // whether the game is hopeless enough for the player to resign
+    fn should_resign(&mut self, player: board::Colour) -> bool {
+        let threshold = match self.resign_threshold {
+            Some(t) => t,
+            None => return false
+        };
+        let hopeless = self.winrate(player) < threshold;
+        let counter = match player {
+            board::Black => &mut self.black_hopeless_moves,
+            board::White => &mut self.white_hopeless_moves
+        };
+        *counter = if hopeless { *counter + 1 } else { 0 };
+        *counter >= resign_moves
     }
 
     fn list_groups(&self) -> String {
-        let mut output = String::from_str("Groups:\n");
-        for (gid, ref grp) in self.goban.get_groups().iter() {
-            output = output.append(format!("{} :: stones : ", gid).as_slice());
-            for &(x, y) in grp.get_stones() {
-                output = output.append(format!("({},{}) ", x, y).as_slice());
+        if self.json_output {
+            list_groups_json(&self.goban)
+        } else {
+            list_groups(&self.goban)
+        }
+    }
+
+    fn set_time_settings(&mut self, settings: clock::TimeSettings) {
+        self.clock.set_settings(settings);
+        self.board_changed();
+    }
+
+    // time_settings main_time byo_yomi_time byo_yomi_stones
+    fn time_settings(&mut self, args: &str) -> (bool, String) {
+        let values: Vec<Option<uint>> = args.words().map(|w| from_str::<uint>(w)).collect();
+        match values.as_slice() {
+            [Some(main_time), Some(byoyomi_time), Some(byoyomi_stones)] => {
+                self.set_time_settings(clock::TimeSettings {
+                    main_time: main_time as f64,
+                    byoyomi_time: byoyomi_time as f64,
+                    byoyomi_stones: byoyomi_stones
+                });
+                (true, String::new())
             }
-            output = output.append("liberties : ");
-            for &(x, y) in grp.get_liberties() {
-                output = output.append(format!("({},{}) ", x, y).as_slice());
+            _ => (false, String::from_str("syntax error"))
+        }
+    }
+
+    // kgs-time_settings none|absolute|byoyomi|canadian, followed by the
+    // times of the system
+    fn kgs_time_settings(&mut self, args: &str) -> (bool, String) {
+        let words: Vec<&str> = args.words().collect();
+        let (system, values) = match words.as_slice() {
+            [system, ..values] => (system, values),
+            [] => return (false, String::from_str("syntax error"))
+        };
+        let values: Vec<Option<uint>> = values.iter().map(|w| from_str::<uint>(*w)).collect();
+        let settings = match (system, values.as_slice()) {
+            ("none", []) => clock::TimeSettings::unlimited(),
+            ("absolute", [Some(main_time)]) => clock::TimeSettings::absolute(main_time as f64),
+            ("byoyomi", [Some(main_time), Some(period_time), Some(periods)]) => {
+                clock::TimeSettings::japanese(main_time as f64, period_time as f64, periods)
+            }
+            ("canadian", [Some(main_time), Some(byoyomi_time), Some(byoyomi_stones)]) => {
+                clock::TimeSettings {
+                    main_time: main_time as f64,
+                    byoyomi_time: byoyomi_time as f64,
+                    byoyomi_stones: byoyomi_stones
+                }
+            }
+            _ => return (false, String::from_str("syntax error"))
+        };
+        self.set_time_settings(settings);
+        (true, String::new())
+    }
+
+    // final_status_list alive|dead|seki, the stones of each group in the
+    // category on a line
+    fn final_status_list(&mut self, args: &str) -> (bool, String) {
+        let wanted = match args.trim() {
+            "alive" => status::Alive,
+            "dead" => status::Dead,
+            "seki" => status::Seki,
+            _ => return (false, String::from_str("syntax error"))
+        };
+        let statuses = status::estimate(&self.goban, final_status_playouts, &mut self.rng);
+        let lines: Vec<String> = statuses.iter().filter(|&&(_, st)| st == wanted).map(|&(gid, _)| {
+            let stones: Vec<(uint, uint)> = self.goban.get_groups()[gid].get_stones().collect();
+            points_to_vertices(stones.as_slice())
+        }).collect();
+        (true, lines.connect("\n"))
+    }
+
+    // handicaps can only be placed before the game starts
+    fn board_is_empty(&self) -> bool {
+        self.goban.move_number() == 0 && self.goban.iter().all(|(_, _, stone)| stone.is_none())
+    }
+
+    // fixed_handicap n, answers the points of the stones
+    fn fixed_handicap(&mut self, args: &str) -> (bool, String) {
+        let stones = match from_str::<uint>(args.trim()) {
+            Some(n) => n,
+            None => return (false, String::from_str("syntax error"))
+        };
+        if !self.board_is_empty() {
+            return (false, String::from_str("board not empty"));
+        }
+        match self.goban.place_fixed_handicap(stones) {
+            Some(points) => {
+                self.board_changed();
+                (true, points_to_vertices(points.as_slice()))
+            }
+            None => (false, String::from_str("invalid number of stones"))
+        }
+    }
+
+    // place_free_handicap n, answers the points chosen for the stones
+    fn place_free_handicap(&mut self, args: &str) -> (bool, String) {
+        let stones = match from_str::<uint>(args.trim()) {
+            Some(n) => n,
+            None => return (false, String::from_str("syntax error"))
+        };
+        if !self.board_is_empty() {
+            return (false, String::from_str("board not empty"));
+        }
+        match self.goban.place_free_handicap(stones) {
+            Some(points) => {
+                self.board_changed();
+                (true, points_to_vertices(points.as_slice()))
+            }
+            None => (false, String::from_str("invalid number of stones"))
+        }
+    }
+
+    // set_free_handicap vertex..., the handicap chosen by the opponent
+    fn set_free_handicap(&mut self, args: &str) -> (bool, String) {
+        let points: Vec<Option<(uint, uint)>> = args.words().map(|w| coords::parse_vertex(w)).collect();
+        if points.iter().any(|p| p.is_none()) {
+            return (false, String::from_str("syntax error"));
+        }
+        let points: Vec<(uint, uint)> = points.move_iter().map(|p| p.unwrap()).collect();
+        if !self.board_is_empty() {
+            return (false, String::from_str("board not empty"));
+        }
+        if self.goban.set_free_handicap(points.as_slice()) {
+            self.board_changed();
+            (true, String::new())
+        } else {
+            (false, String::from_str("bad vertex list"))
+        }
+    }
+
+    // loadsgf filename [move_number], the position is set up as before the
+    // given move, after the whole main line without it. Answers the
+    // colour to move.
+    fn loadsgf(&mut self, args: &str) -> (bool, String) {
+        let words: Vec<&str> = args.words().collect();
+        let (file, until) = match words.as_slice() {
+            [file] => (file, None),
+            [file, number] => match from_str::<uint>(number) {
+                Some(n) if n > 0 => (file, Some(n)),
+                _ => return (false, String::from_str("syntax error"))
+            },
+            _ => return (false, String::from_str("syntax error"))
+        };
+        // the loaded game keeps the rules, the history limit, and the komi
+        // if the file has none
+        let mut goban = self.goban.clone_without_history();
+        // compacted moves could not be taken back to the requested one
+        goban.set_history_limit(None);
+        match sgf::load_on(&Path::new(file), &mut goban) {
+            Ok(()) => {}
+            Err(e) => {
+                let _ = writeln!(&mut io::stderr(), "{}", e);
+                return (false, String::from_str("cannot load file"));
+            }
+        }
+        match until {
+            Some(n) => while goban.move_number() >= n && goban.undo() {},
+            None => {}
+        }
+        let to_move = goban.to_move();
+        goban.set_history_limit(self.goban.get_history_limit());
+        self.goban = goban;
+        self.game_replaced();
+        self.board_changed();
+        (true, String::from_str(match to_move { board::Black => "black", board::White => "white" }))
+    }
+
+    // printsgf [filename], the game is written to the file, or in the
+    // answer without a file name
+    fn printsgf(&self, args: &str) -> (bool, String) {
+        let game = sgf::write_game(&self.goban);
+        match args.trim() {
+            "" => (true, game),
+            file => match File::create(&Path::new(file)).write_str(game.as_slice()) {
+                Ok(()) => (true, String::new()),
+                Err(e) => {
+                    let _ = writeln!(&mut io::stderr(), "cannot write {}: {}", file, e);
+                    (false, String::from_str("cannot write file"))
+                }
             }
-            output = output.append("\n");
         }
-        output
+    }
+
+    // the move the engine would play, the board is left untouched
+    fn choose_move(&mut self, player: board::Colour) -> board::Vertex {
+        if self.cleanup || self.goban.both_passed() {
+            let budget = self.move_budget(player);
+            randomplay::choose_cleanup_move(&self.goban, player, budget, &mut self.rng)
+        } else {
+            randomplay::choose_move(&self.goban, player, &mut self.rng)
+        }
+    }
+
+    // plays the move of the controller, or tells why it is illegal
+    fn play(&mut self, player: board::Colour, move: api::Move) -> Result<(), String> {
+        if self.goban.resigned().is_some() {
+            // the game is over, and has been archived already
+            return Err(String::from_str("game is over"));
+        }
+        match move {
+            api::Pass => {
+                self.goban.pass(player);
+                self.board_changed();
+                Ok(())
+            }
+            api::Stone(vrtx) => {
+                let (x, y) = coords::from_vertex(vrtx);
+                let simple_ko = self.goban.get_current_ko() == Some((x, y));
+                match self.goban.play(player, x, y) {
+                    Ok(()) => { self.board_changed(); Ok(()) }
+                    Err(board::Ko) if !simple_ko => Err(String::from_str("illegal move: superko")),
+                    Err(reason) => Err(format!("illegal move: {}", reason))
+                }
+            }
+            api::Resign => {
+                self.goban.resign(player);
+                self.game_over();
+                Ok(())
+            }
+        }
+    }
+
+    // play colour vertex, answered through the custom commands so that an
+    // illegal move answers its reason: ko, superko, suicide or occupied
+    fn play_command(&mut self, args: &str) -> (bool, String) {
+        let words: Vec<&str> = args.words().collect();
+        let (colour, move) = match words.as_slice() {
+            [colour, vertex] => match (coords::parse_colour(colour), coords::parse_move(vertex)) {
+                (Some(c), Some(m)) => (coords::from_api_colour(c), m),
+                _ => return (false, String::from_str("syntax error"))
+            },
+            _ => return (false, String::from_str("syntax error"))
+        };
+        match self.play(colour, move) {
+            Ok(()) => (true, String::new()),
+            Err(reason) => (false, reason)
+        }
+    }
+
+    // reg_genmove colour, answers the move without playing it
+    fn reg_genmove(&mut self, args: &str) -> (bool, String) {
+        let colour = match coords::parse_colour(args.trim()) {
+            Some(c) => coords::from_api_colour(c),
+            None => return (false, String::from_str("syntax error"))
+        };
+        if self.goban.resigned().is_some() {
+            return (false, String::from_str("game is over"));
+        }
+        let move = self.choose_move(colour);
+        // board sizes are limited to what GTP can represent
+        (true, coords::format_move(coords::to_api_move(move).unwrap_or(api::Pass)))
+    }
+
+    // kgs-genmove_cleanup colour, genmove in cleanup mode: no pass while
+    // dead opponent stones are left on the board
+    fn kgs_genmove_cleanup(&mut self, args: &str) -> (bool, String) {
+        let colour = match coords::parse_colour(args.trim()) {
+            Some(c) => c,
+            None => return (false, String::from_str("syntax error"))
+        };
+        if self.goban.resigned().is_none() {
+            self.cleanup = true;
+        }
+        (true, coords::format_move(self.gtp_genmove(colour)))
+    }
+
+    // cg_move_times, the elapsed and CPU thinking times of the generated
+    // moves, one move per line
+    fn move_times(&self) -> String {
+        let lines: Vec<String> = self.goban.history_entries().iter().filter_map(|entry| {
+            match (entry.time_spent, entry.cpu_time_spent) {
+                (Some(wall), Some(cpu)) => {
+                    Some(format!("{} {} {} {:.3} {:.3}", entry.number, entry.player, entry.move, wall, cpu))
+                }
+                _ => None
+            }
+        }).collect();
+        lines.connect("\n")
+    }
+
+    // cg_undo_n n, takes back n moves at once, or none if there are fewer
+    fn undo_n(&mut self, args: &str) -> (bool, String) {
+        let n = match from_str::<uint>(args.trim()) {
+            Some(n) => n,
+            None => return (false, String::from_str("syntax error"))
+        };
+        if self.goban.resigned().is_none() && self.goban.undo_n(n) {
+            self.moves_undone();
+            self.board_changed();
+            (true, String::new())
+        } else {
+            (false, String::from_str("cannot undo"))
+        }
+    }
+
+    // time_left colour time stones
+    fn time_left(&mut self, args: &str) -> (bool, String) {
+        let words: Vec<&str> = args.words().collect();
+        let (colour, time, stones) = match words.as_slice() {
+            [colour, time, stones] => (coords::parse_colour(colour).map(coords::from_api_colour),
+                                       from_str::<uint>(time), from_str::<uint>(stones)),
+            _ => return (false, String::from_str("syntax error"))
+        };
+        match (colour, time, stones) {
+            (Some(c), Some(t), Some(s)) => {
+                self.clock.set_time_left(c, clock::TimeLeft { time: t as f64, stones: s });
+                self.board_changed();
+                (true, String::new())
+            }
+            _ => (false, String::from_str("syntax error"))
+        }
+    }
+
+    // thinking time for the next move of the player
+    fn move_budget(&self, player: board::Colour) -> Option<f64> {
+        self.clock.move_budget(player, self.goban.get_size(), self.goban.move_number())
     }
 }
 
@@ -46,46 +750,68 @@ impl api::GoBot for ClockGoBot{
 
     fn gtp_clear_board(&mut self) {
         self.goban.clear();
+        self.game_replaced();
+        self.board_changed();
     }
 
     fn gtp_komi(&mut self, komi: f32) {
-        self.komi = komi;
+        self.goban.set_komi(komi);
+        self.board_changed();
     }
 
     fn gtp_boardsize(&mut self, size: uint) -> Result<(), api::GTPError> {
         match self.goban.resize(size) {
-            true => Ok(()),
+            true => { self.game_replaced(); self.board_changed(); Ok(()) },
             false => Err(api::InvalidBoardSize)
         }
     }
 
     fn gtp_play(&mut self, move: api::ColouredMove) -> Result<(), api::GTPError> {
-        match move {
-            api::ColouredMove{player: col, move: api::Pass} => {
-                self.goban.pass(match col { api::White => board::White, api::Black => board::Black});
-                Ok(())},
-            api::ColouredMove{player: col, move: api::Stone(vrtx)} => {
-                let (x, y) = vrtx.to_coords();
-                match self.goban.play(match col { api::White => board::White, api::Black => board::Black}, x as uint, y as uint) {
-                        true => Ok(()),
-                        false => Err(api::InvalidMove)
-                    }
-                },
-            api::ColouredMove{player: _, move: api::Resign} => {Ok(())}
+        let api::ColouredMove{player: col, move: mv} = move;
+        match self.play(coords::from_api_colour(col), mv) {
+            Ok(()) => Ok(()),
+            Err(reason) => {
+                // the GTP error cannot carry the reason, it goes to the
+                // diagnostics stream; the custom play command answers it
+                let _ = writeln!(&mut io::stderr(), "{}", reason);
+                Err(api::InvalidMove)
+            }
         }
     }
 
     fn gtp_genmove(&mut self, player: api::Colour) -> api::Move {
-        match randomplay::genmove(&mut self.goban,
-                match player { api::Black => board::Black, api::White => board::White }
-            ) {
-            board::Put(x, y) => api::Stone(api::Vertex::from_coords(x as u8, y as u8).unwrap()),
-            board::Pass => api::Pass
+        if self.goban.resigned().is_some() {
+            // the game is over, no move is played until clear_board; the
+            // answer cannot be an error, and a pass would count as a move
+            return api::Resign;
+        }
+        let colour = coords::from_api_colour(player);
+        let start = time::precise_time_s();
+        let cpu_start = clock::cpu_time();
+        if self.goban.both_passed() {
+            self.cleanup = true;
+        }
+        // no resignation in the cleanup phase, the score decides by then
+        if !self.cleanup && self.should_resign(colour) {
+            self.goban.resign(colour);
+            self.game_over();
+            return api::Resign;
         }
+        let move = self.choose_move(colour);
+        // the move was checked against this position
+        let legal = self.goban.play_move(colour, move).is_ok();
+        debug_assert!(legal);
+        self.goban.set_last_move_time(time::precise_time_s() - start, clock::cpu_time() - cpu_start);
+        self.board_changed();
+        // board sizes are limited to what GTP can represent
+        coords::to_api_move(move).unwrap_or(api::Pass)
     }
 
     fn gtp_undo(&mut self) -> Result<(), api::GTPError> {
-        if self.goban.undo() {
+        // a resigned game is archived, it cannot be resumed
+        if self.goban.resigned().is_none() && self.goban.undo() {
+            self.moves_undone();
+            self.board_changed();
             Ok(())
         } else {
             Err(api::CannotUndo)
@@ -95,40 +821,64 @@ impl api::GoBot for ClockGoBot{
     fn gtp_showboard(&self) -> Result<(uint, Vec<api::Vertex>, Vec<api::Vertex>, uint, uint), api::GTPError> {
         let mut black_stones = Vec::new();
         let mut white_stones = Vec::new();
-        let &stones = self.goban.get_board();
-        let size = self.goban.get_size();
-        for i in range(0, size) {
-            for j in range(0, size) {
-                match stones[i][j] {
-                    board::Stone(board::Black, _) => {
-                        black_stones.push(api::Vertex::from_coords((i+1) as u8, (j+1) as u8).unwrap());
-                    },
-                    board::Stone(board::White, _) => {
-                        white_stones.push(api::Vertex::from_coords((i+1) as u8, (j+1) as u8).unwrap());
-                    },
-                    board::Empty => {}
-                }
+        for (x, y, stone) in self.goban.iter() {
+            match stone {
+                Some(board::Black) => black_stones.extend(coords::to_vertex(x, y).move_iter()),
+                Some(board::White) => white_stones.extend(coords::to_vertex(x, y).move_iter()),
+                None => {}
             }
         }
+        let size = self.goban.get_size();
         let (bd, wd) = self.goban.get_deads();
         Ok((size, black_stones, white_stones, bd, wd))
     }
 
-    #[allow(unused_variable)]
     fn gtp_custom_command(&mut self, command: &str, args: &str) -> (bool, String) {
-        if command == "cg_list_groups" {
-            (true, self.list_groups())
-        } else {
-            (false, String::from_str("unknown command"))
+        match command {
+            "cg_list_groups" => (true, self.list_groups()),
+            "cg_showboard" => (true, show_board(&self.goban)),
+            "cputime" => (true, format!("{:.3}", clock::cpu_time())),
+            "cg_move_times" => (true, self.move_times()),
+            "cg_score_estimate" => score_estimate(&self.goban, args, self.json_output, &mut self.rng),
+            "cg_undo_n" => self.undo_n(args),
+            "cg_legal_moves" => legal_moves(&self.goban, args, self.json_output),
+            "cg_liberties" => liberties(&self.goban, args, self.json_output),
+            "cg_seed" => match from_str::<uint>(args.trim()) {
+                Some(seed) => { self.set_seed(seed); (true, String::new()) }
+                None => (false, String::from_str("syntax error"))
+            },
+            "cg_hash" => (true, hash(&self.goban, self.json_output)),
+            "cg_winrate" => {
+                let player = self.goban.to_move();
+                let rate = self.winrate(player);
+                (true, winrate(player, rate, self.json_output))
+            }
+            "time_settings" => self.time_settings(args),
+            "time_left" => self.time_left(args),
+            "kgs-time_settings" => self.kgs_time_settings(args),
+            "final_status_list" => self.final_status_list(args),
+            "fixed_handicap" => self.fixed_handicap(args),
+            "place_free_handicap" => self.place_free_handicap(args),
+            "set_free_handicap" => self.set_free_handicap(args),
+            "loadsgf" => self.loadsgf(args),
+            "printsgf" => self.printsgf(args),
+            "reg_genmove" => self.reg_genmove(args),
+            "kgs-genmove_cleanup" => self.kgs_genmove_cleanup(args),
+            "play" => self.play_command(args),
+            // cg_rules [rule...], sets the given rules and answers those in force
+            "cg_rules" => match self.set_rules(args) {
+                true => (true, self.rules()),
+                false => (false, String::from_str("syntax error"))
+            },
+            _ => (false, String::from_str("unknown command"))
         }
-
     }
 
     fn gtp_known_custom_command(&self, command: &str) -> bool {
-        command == "cg_list_groups"
+        custom_commands.iter().any(|&c| c == command)
     }
 
     fn gtp_list_custom_commands(&self) -> Vec<String> {
-        vec!(String::from_str("cg_list_groups"))
+        custom_commands.iter().map(|&c| String::from_str(c)).collect()
     }
 }
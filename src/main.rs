@@ -1,14 +1,155 @@
 #![feature(macro_rules)]
 
 extern crate gtprust;
+extern crate libc;
+extern crate time;
 
+use std::io;
+use std::os;
+use std::sync::{Arc, Mutex};
+
+pub mod bitboard;
 pub mod board;
+pub mod clock;
+pub mod coords;
+pub mod diagram;
+pub mod differential;
+pub mod smallvec;
+pub mod gametree;
 pub mod gtp;
+pub mod monitor;
+pub mod movegen;
+pub mod persist;
+pub mod proxy;
+pub mod regress;
+pub mod sgf;
 pub mod statics;
+pub mod status;
 
 pub mod randomplay;
 
+// stdout is reserved for the GTP session
+fn fail(message: String) {
+    let _ = writeln!(&mut io::stderr(), "{}", message);
+    os::set_exit_status(1);
+}
+
+// runs the given .tst files and reports the totals
+fn run_regressions(files: &[String]) {
+    let mut out = io::stdout();
+    let (mut passed, mut failed) = (0u, 0u);
+    for file in files.iter() {
+        match regress::run_file(&Path::new(file.as_slice()), &mut out) {
+            Ok(summary) => {
+                passed += summary.passed;
+                failed += summary.failed + summary.unexpected_passes;
+            }
+            Err(e) => { fail(e); return; }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        os::set_exit_status(1);
+    }
+}
+
+// runs the given diagram case files and reports the totals
+fn run_diagram_tests(files: &[String]) {
+    let mut out = io::stdout();
+    let (mut passed, mut failed) = (0u, 0u);
+    for file in files.iter() {
+        match diagram::run_file(&Path::new(file.as_slice()), &mut out) {
+            Ok((p, f)) => { passed += p; failed += f; }
+            Err(e) => { fail(e); return; }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        os::set_exit_status(1);
+    }
+}
+
 fn main() {
     let mut bot = gtp::ClockGoBot::new();
+    let args = os::args();
+    let mut i = 1u;
+    while i < args.len() {
+        match args[i].as_slice() {
+            "--monitor-socket" if i + 1 < args.len() => {
+                let shared = Arc::new(Mutex::new(board::Board::new().snapshot()));
+                let path = Path::new(args[i+1].as_slice());
+                match monitor::spawn_listener(path, shared.clone()) {
+                    Ok(()) => bot.set_monitor(shared),
+                    Err(e) => { fail(format!("cannot listen on monitor socket: {}", e)); return; }
+                }
+                i += 1;
+            }
+            "--json" => bot.set_json_output(true),
+            "--history-limit" if i + 1 < args.len() => {
+                match from_str::<uint>(args[i+1].as_slice()) {
+                    Some(limit) => bot.set_history_limit(Some(limit)),
+                    None => { fail(format!("invalid history limit: {}", args[i+1])); return; }
+                }
+                i += 1;
+            }
+            "--rules" if i + 1 < args.len() => {
+                if !bot.set_rules(args[i+1].as_slice()) {
+                    fail(format!("invalid rules: {}", args[i+1]));
+                    return;
+                }
+                i += 1;
+            }
+            "--seed" if i + 1 < args.len() => {
+                match from_str::<uint>(args[i+1].as_slice()) {
+                    Some(seed) => bot.set_seed(seed),
+                    None => { fail(format!("invalid seed: {}", args[i+1])); return; }
+                }
+                i += 1;
+            }
+            "--resign-threshold" if i + 1 < args.len() => {
+                match from_str::<f32>(args[i+1].as_slice()) {
+                    Some(t) if t >= 0.0 && t <= 1.0 => bot.set_resign_threshold(Some(t)),
+                    _ => { fail(format!("invalid resign threshold: {}", args[i+1])); return; }
+                }
+                i += 1;
+            }
+            "--autosave" if i + 1 < args.len() => {
+                match bot.set_autosave(Path::new(args[i+1].as_slice())) {
+                    Ok(()) => {}
+                    Err(e) => { fail(format!("cannot resume saved game: {}", e)); return; }
+                }
+                i += 1;
+            }
+            "--diagram-tests" => {
+                run_diagram_tests(args.slice_from(i + 1));
+                return;
+            }
+            "--differential" if i + 2 < args.len() => {
+                let games = match from_str::<uint>(args[i+1].as_slice()) {
+                    Some(n) => n,
+                    None => { fail(format!("invalid number of games: {}", args[i+1])); return; }
+                };
+                match differential::run(games, args[i+2].as_slice(), args.slice_from(i + 3), &mut io::stdout()) {
+                    Ok(0) => {}
+                    Ok(n) => fail(format!("{} games out of {} showed discrepancies", n, games)),
+                    Err(e) => fail(format!("reference engine error: {}", e))
+                }
+                return;
+            }
+            "--regress" => {
+                run_regressions(args.slice_from(i + 1));
+                return;
+            }
+            "--proxy" if i + 1 < args.len() => {
+                match proxy::run(args[i+1].as_slice(), args.slice_from(i + 2)) {
+                    Ok(()) => {}
+                    Err(e) => fail(format!("proxy error: {}", e))
+                }
+                return;
+            }
+            other => { fail(format!("unknown argument: {}", other)); return; }
+        }
+        i += 1;
+    }
     gtprust::main_loop(&mut bot);
 }
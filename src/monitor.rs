@@ -0,0 +1,104 @@
+//! A read-only GTP session served on a Unix domain socket, allowing an
+//! operator to inspect the bot while it is attached to a server.
+
+use std::io::{Acceptor, Listener, BufferedReader, IoResult};
+use std::io::net::unix::{UnixListener, UnixStream};
+use std::rand::task_rng;
+use std::sync::{Arc, Mutex};
+
+use board;
+use gtp;
+use statics;
+use status;
+
+static monitor_commands : &'static [&'static str] = &["protocol_version", "name", "version",
+    "known_command", "list_commands", "showboard", "cg_list_groups", "cg_showboard", "cg_hash",
+    "cg_legal_moves", "cg_liberties", "cg_score_estimate", "cg_winrate", "quit"];
+
+/// Starts listening on the given socket path in a background task.
+/// Every connection gets its own session answering commands from the
+/// latest published position.
+pub fn spawn_listener(path: Path, shared: Arc<Mutex<board::Snapshot>>) -> IoResult<()> {
+    let listener = try!(UnixListener::bind(&path));
+    let mut acceptor = try!(listener.listen());
+    spawn(proc() {
+        for stream in acceptor.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let session_board = shared.clone();
+                    spawn(proc() {
+                        // a broken connection only ends this session
+                        let _ = serve(stream, session_board);
+                    });
+                }
+                Err(_) => {}
+            }
+        }
+    });
+    Ok(())
+}
+
+fn serve(stream: UnixStream, shared: Arc<Mutex<board::Snapshot>>) -> IoResult<()> {
+    let mut output = stream.clone();
+    let mut input = BufferedReader::new(stream);
+    for line in input.lines() {
+        let line = try!(line);
+        let mut words = line.as_slice().words();
+        let mut first = match words.next() {
+            Some(w) => w,
+            None => continue
+        };
+        // optional command id
+        let id = match from_str::<uint>(first) {
+            Some(n) => {
+                first = match words.next() { Some(w) => w, None => continue };
+                Some(n)
+            }
+            None => None
+        };
+        let args: Vec<&str> = words.collect();
+        let (success, reply) = answer(first, args.as_slice(), &shared);
+        let id_str = match id { Some(n) => format!("{}", n), None => String::new() };
+        try!(output.write_str(format!("{}{} {}\n\n",
+            if success { "=" } else { "?" }, id_str, reply).as_slice()));
+        if first == "quit" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// only holds the lock long enough to grab the latest position
+fn latest(shared: &Arc<Mutex<board::Snapshot>>) -> board::Snapshot {
+    shared.lock().clone()
+}
+
+fn answer(command: &str, args: &[&str], shared: &Arc<Mutex<board::Snapshot>>) -> (bool, String) {
+    match command {
+        "protocol_version" => (true, String::from_str("2")),
+        "name" => (true, String::from_str(statics::clockgo_name)),
+        "version" => (true, String::from_str(statics::clockgo_version)),
+        "known_command" => {
+            let known = args.len() > 0 && monitor_commands.contains(&args[0]);
+            (true, String::from_str(if known { "true" } else { "false" }))
+        }
+        "list_commands" => (true, monitor_commands.connect("\n")),
+        "cg_list_groups" => (true, gtp::list_groups(&*latest(shared))),
+        // the snapshot has no history, the last move is not marked
+        "showboard" | "cg_showboard" => (true, gtp::show_board(&*latest(shared))),
+        "cg_hash" => (true, gtp::hash(&*latest(shared), false)),
+        "cg_legal_moves" => gtp::legal_moves(&*latest(shared), args.connect(" ").as_slice(), false),
+        "cg_liberties" => gtp::liberties(&*latest(shared), args.connect(" ").as_slice(), false),
+        "cg_score_estimate" => {
+            gtp::score_estimate(&*latest(shared), args.connect(" ").as_slice(), false, &mut task_rng())
+        }
+        "cg_winrate" => {
+            let goban = latest(shared);
+            let player = goban.to_move();
+            let rate = status::winrate(&*goban, player, gtp::winrate_playouts, &mut task_rng());
+            (true, gtp::winrate(player, rate, false))
+        }
+        "quit" => (true, String::new()),
+        _ => (false, String::from_str("command not available in read-only session"))
+    }
+}
@@ -0,0 +1,140 @@
+//! Generation of candidate moves in tactical priority order: captures
+//! first, then atari escapes, atari threats, liberties of weak groups and
+//! finally big points, moves of the same priority coming out in random
+//! order. Ko threats are listed separately, by decreasing size.
+
+use std::cmp::min;
+use std::collections::PriorityQueue;
+use std::rand::Rng;
+
+use board;
+
+/// Kinds of candidate moves, from the least to the most urgent.
+#[deriving(PartialEq, Eq, PartialOrd, Ord, Show)]
+pub enum Priority {
+    /// Extension on the third or fourth line in an empty area.
+    BigPoint,
+    /// Takes a liberty of a weak group, to defend it or to attack it.
+    WeakGroup,
+    /// Puts an opponent group in atari.
+    AtariThreat,
+    /// Extends a friendly group in atari.
+    AtariEscape,
+    /// Captures an opponent group.
+    Capture
+}
+
+/// Candidate moves for a player, yielded by decreasing priority as
+/// (priority, x, y). Candidates are not checked for legality, and a point
+/// may come out several times with different priorities.
+pub struct Candidates {
+    queue: PriorityQueue<(Priority, u32, uint, uint)>
+}
+
+impl Iterator<(Priority, uint, uint)> for Candidates {
+    fn next(&mut self) -> Option<(Priority, uint, uint)> {
+        self.queue.pop().map(|(priority, _, x, y)| (priority, x, y))
+    }
+}
+
+// a point is a big point if it is on the 3rd or 4th line and no stone is
+// within a manhattan distance of 2
+fn is_big_point(goban: &board::Board, x: uint, y: uint) -> bool {
+    let size = goban.get_size();
+    let line = goban.line(x, y);
+    if line != 3 && line != 4 {
+        return false;
+    }
+    for i in range(if x > 2 { x - 2 } else { 1 }, min(x + 2, size) + 1) {
+        for j in range(if y > 2 { y - 2 } else { 1 }, min(y + 2, size) + 1) {
+            let dist = (if i > x { i - x } else { x - i }) + (if j > y { j - y } else { y - j });
+            if dist <= 2 && goban.at(i, j).is_some() {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Computes the candidate moves of the player in the current position,
+/// using the given generator to order moves of equal priority.
+pub fn candidates<R: Rng>(goban: &board::Board, player: board::Colour, rng: &mut R) -> Candidates {
+    let mut queue = PriorityQueue::new();
+    for (gid, grp) in goban.get_groups().iter() {
+        let (x, y) = grp.get_stones().next().unwrap();
+        let own = goban.at(x, y) == Some(player);
+        let priority = match (own, grp.liberty_count()) {
+            (false, 1) => Capture,
+            (true, 1) => AtariEscape,
+            (false, 2) => AtariThreat,
+            _ if goban.group_strength(gid).unwrap() < board::weak_group_strength => WeakGroup,
+            _ => continue
+        };
+        for (a, b) in grp.get_liberties() {
+            queue.push((priority, rng.gen::<u32>(), a, b));
+        }
+    }
+    if goban.get_size() >= 9 {
+        for x in range(1u, goban.get_size()+1) {
+            for y in range(1u, goban.get_size()+1) {
+                if goban.at(x, y).is_none() && is_big_point(goban, x, y) {
+                    queue.push((BigPoint, rng.gen::<u32>(), x, y));
+                }
+            }
+        }
+    }
+    Candidates { queue: queue }
+}
+
+/// The most urgent candidates only, captures and atari escapes, cheap
+/// enough to be computed before every move of a playout.
+pub fn urgent_candidates<R: Rng>(goban: &board::Board, player: board::Colour, rng: &mut R) -> Candidates {
+    let mut queue = PriorityQueue::new();
+    for (_, grp) in goban.get_groups().iter() {
+        if grp.liberty_count() != 1 {
+            continue;
+        }
+        let (x, y) = grp.get_stones().next().unwrap();
+        let priority = if goban.at(x, y) == Some(player) { AtariEscape } else { Capture };
+        for (a, b) in grp.get_liberties() {
+            queue.push((priority, rng.gen::<u32>(), a, b));
+        }
+    }
+    Candidates { queue: queue }
+}
+
+/// A move threatening to capture opponent stones, see ko_threats.
+pub struct KoThreat {
+    pub x: uint,
+    pub y: uint,
+    /// Rough value of the threat in points: twice the number of stones
+    /// put in atari, counting the prisoners and the territory they hold.
+    pub size: uint
+}
+
+/// Legal moves of the player which put opponent groups in atari, with the
+/// biggest threats first. A point threatening several groups adds up
+/// their sizes.
+pub fn ko_threats(goban: &board::Board, player: board::Colour) -> Vec<KoThreat> {
+    let mut threats: Vec<KoThreat> = Vec::new();
+    for (_, grp) in goban.get_groups().iter() {
+        let (x, y) = grp.get_stones().next().unwrap();
+        let own = goban.at(x, y) == Some(player);
+        if own || grp.liberty_count() != 2 {
+            continue;
+        }
+        for (a, b) in grp.get_liberties() {
+            if !goban.is_legal(player, a, b) {
+                continue;
+            }
+            let size = 2 * grp.stone_count();
+            match threats.mut_iter().find(|t| t.x == a && t.y == b) {
+                Some(threat) => { threat.size += size; continue; }
+                None => {}
+            }
+            threats.push(KoThreat { x: a, y: b, size: size });
+        }
+    }
+    threats.sort_by(|t1, t2| t2.size.cmp(&t1.size));
+    threats
+}
@@ -0,0 +1,146 @@
+//! Periodic saving of the game in progress, so that a restarted bot can
+//! reload it and continue instead of forfeiting.
+//!
+//! The file is plain text, one directive per line: the starting position
+//! with its setup stones (such as a handicap) and the player to move
+//! first, the time settings and the time left to each player when known,
+//! then the moves:
+//!
+//! ```text
+//! boardsize 19
+//! komi 0.5
+//! setup B 4 4
+//! setup B 16 16
+//! start W
+//! time_settings 600 30 5
+//! time_left W 540 0
+//! W 16 4
+//! B pass
+//! ```
+//!
+//! Archived games end with a `result` line, e.g. `result B+R`.
+
+use std::io;
+use std::io::{File, IoResult};
+use std::io::fs;
+
+use board;
+use clock;
+
+/// Writes the current game and its clock to the given path. The file is
+/// replaced atomically so a crash mid-write never leaves a truncated save.
+pub fn save(path: &Path, goban: &board::Board, game_clock: &clock::GameClock) -> IoResult<()> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = try!(File::create(&tmp));
+        let record = goban.record();
+        try!(file.write_str(format!("boardsize {}\nkomi {}\n", record.size, record.komi).as_slice()));
+        for &(colour, x, y) in record.setup.iter() {
+            try!(file.write_str(format!("setup {} {} {}\n", colour, x, y).as_slice()));
+        }
+        match record.start_player {
+            Some(colour) => try!(file.write_str(format!("start {}\n", colour).as_slice())),
+            None => {}
+        }
+        let settings = game_clock.settings;
+        try!(file.write_str(format!("time_settings {} {} {}\n", settings.main_time, settings.byoyomi_time,
+                                    settings.byoyomi_stones).as_slice()));
+        for &colour in [board::Black, board::White].iter() {
+            match game_clock.time_left(colour) {
+                Some(left) => try!(file.write_str(format!("time_left {} {} {}\n", colour, left.time,
+                                                          left.stones).as_slice())),
+                None => {}
+            }
+        }
+        for &(colour, move) in record.moves.iter() {
+            try!(match move {
+                board::Put(x, y) => file.write_str(format!("{} {} {}\n", colour, x, y).as_slice()),
+                board::Pass => file.write_str(format!("{} pass\n", colour).as_slice())
+            });
+        }
+    }
+    fs::rename(&tmp, path)
+}
+
+/// Archives a finished game: the save file gets its result appended and
+/// is moved aside with a ".finished.N" extension, N counting the games
+/// archived next to it, so that it is kept but no longer resumed on
+/// restart.
+pub fn archive(path: &Path, goban: &board::Board, game_clock: &clock::GameClock,
+               result: &board::GameResult) -> IoResult<()> {
+    try!(save(path, goban, game_clock));
+    {
+        let mut file = try!(File::open_mode(path, io::Append, io::Write));
+        try!(file.write_str(format!("result {}\n", result).as_slice()));
+    }
+    let mut n = 1u;
+    while path.with_extension(format!("finished.{}", n)).exists() {
+        n += 1;
+    }
+    fs::rename(path, &path.with_extension(format!("finished.{}", n)))
+}
+
+/// Reads a game and its clock back from the given path. Files without
+/// time settings give a game without time limit.
+pub fn load(path: &Path) -> Result<(board::GameRecord, clock::GameClock), String> {
+    let contents = match File::open(path).read_to_string() {
+        Ok(c) => c,
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+    };
+    let mut game = board::GameRecord::new(19, 0.0);
+    let mut game_clock = clock::GameClock::new();
+    for (n, line) in contents.as_slice().lines().enumerate() {
+        let words: Vec<&str> = line.words().collect();
+        let parsed = match words.as_slice() {
+            [] => Some(()),
+            ["boardsize", size] => from_str::<uint>(size).map(|s| { game.size = s; }),
+            ["komi", komi] => from_str::<f32>(komi).map(|k| { game.komi = k; }),
+            ["setup", colour, x, y] => match (parse_colour(colour), from_str::<uint>(x), from_str::<uint>(y)) {
+                (Some(c), Some(x), Some(y)) => { game.setup.push((c, x, y)); Some(()) }
+                _ => None
+            },
+            ["start", colour] => parse_colour(colour).map(|c| { game.start_player = Some(c); }),
+            ["time_settings", main_time, byoyomi_time, byoyomi_stones] => {
+                match (from_str::<f64>(main_time), from_str::<f64>(byoyomi_time),
+                       from_str::<uint>(byoyomi_stones)) {
+                    (Some(m), Some(t), Some(s)) => {
+                        game_clock.set_settings(clock::TimeSettings {
+                            main_time: m,
+                            byoyomi_time: t,
+                            byoyomi_stones: s
+                        });
+                        Some(())
+                    }
+                    _ => None
+                }
+            }
+            ["time_left", colour, time, stones] => {
+                match (parse_colour(colour), from_str::<f64>(time), from_str::<uint>(stones)) {
+                    (Some(c), Some(t), Some(s)) => {
+                        game_clock.set_time_left(c, clock::TimeLeft { time: t, stones: s });
+                        Some(())
+                    }
+                    _ => None
+                }
+            }
+            [colour, "pass"] => parse_colour(colour).map(|c| { game.moves.push((c, board::Pass)); }),
+            [colour, x, y] => match (parse_colour(colour), from_str::<uint>(x), from_str::<uint>(y)) {
+                (Some(c), Some(x), Some(y)) => { game.moves.push((c, board::Put(x, y))); Some(()) }
+                _ => None
+            },
+            _ => None
+        };
+        if parsed.is_none() {
+            return Err(format!("{}:{}: invalid line \"{}\"", path.display(), n + 1, line));
+        }
+    }
+    Ok((game, game_clock))
+}
+
+fn parse_colour(s: &str) -> Option<board::Colour> {
+    match s {
+        "B" => Some(board::Black),
+        "W" => Some(board::White),
+        _ => None
+    }
+}
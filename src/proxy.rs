@@ -0,0 +1,207 @@
+//! Relay mode: clockgo sits between a controller and another GTP engine,
+//! forwarding every command, logging both sides on stderr and checking
+//! every move against its own Board, flagging any disagreement. Komi,
+//! handicaps and loaded games are mirrored on that Board, and a
+//! resignation ends the game until clear_board.
+
+use std::ascii::StrAsciiExt;
+use std::io;
+use std::io::{BufferedReader, IoResult};
+use std::io::pipe::PipeStream;
+use std::io::process::{Command, Process};
+
+use gtprust::api;
+
+use board;
+use coords;
+use sgf;
+
+/// A GTP engine running as a child process.
+pub struct Engine {
+    process: Process,
+    input: PipeStream,
+    output: BufferedReader<PipeStream>
+}
+
+impl Engine {
+    pub fn spawn(program: &str, args: &[String]) -> IoResult<Engine> {
+        let mut process = try!(Command::new(program).args(args).spawn());
+        let input = process.stdin.take().unwrap();
+        let output = BufferedReader::new(process.stdout.take().unwrap());
+        Ok(Engine { process: process, input: input, output: output })
+    }
+
+    /// Sends a command line and returns the full response, including
+    /// its terminating empty line.
+    pub fn send(&mut self, command: &str) -> IoResult<String> {
+        try!(self.input.write_str(command));
+        if !command.ends_with("\n") {
+            try!(self.input.write_str("\n"));
+        }
+        try!(self.input.flush());
+        read_response(&mut self.output)
+    }
+
+    /// Waits for the engine to exit.
+    pub fn wait(mut self) -> IoResult<()> {
+        try!(self.process.wait());
+        Ok(())
+    }
+}
+
+/// Runs the given engine and relays the GTP session on stdin/stdout to
+/// it until the controller quits or closes the connection.
+pub fn run(program: &str, args: &[String]) -> IoResult<()> {
+    let mut engine = try!(Engine::spawn(program, args));
+    let mut controller = io::stdin();
+    let mut out = io::stdout();
+    let mut log = io::stderr();
+    let mut goban = board::Board::new();
+    for line in controller.lines() {
+        let line = try!(line);
+        try!(log.write_str(format!("> {}", line).as_slice()));
+        let response = try!(engine.send(line.as_slice()));
+        try!(log.write_str(format!("< {}", response).as_slice()));
+        try!(out.write_str(response.as_slice()));
+        try!(out.flush());
+        match check(&mut goban, line.as_slice(), response.as_slice()) {
+            Some(problem) => try!(log.write_str(format!("!! {}\n", problem).as_slice())),
+            None => {}
+        }
+        if line.as_slice().words().any(|w| w == "quit") {
+            break;
+        }
+    }
+    engine.wait()
+}
+
+// a GTP response is terminated by an empty line
+fn read_response<B: Buffer>(engine: &mut B) -> IoResult<String> {
+    let mut response = String::new();
+    loop {
+        let line = try!(engine.read_line());
+        let blank = line.as_slice().trim().is_empty();
+        if blank && !response.is_empty() {
+            response.push_str("\n");
+            return Ok(response);
+        }
+        if !blank {
+            response.push_str(line.as_slice());
+        }
+    }
+}
+
+// plays a move accepted or generated by the engine on our board,
+// returns whether our board found it legal; a resignation ends the game
+fn apply(goban: &mut board::Board, colour: api::Colour, move: api::Move) -> bool {
+    match (move, coords::from_api_move(move)) {
+        (api::Resign, _) => { goban.resign(coords::from_api_colour(colour)); true }
+        (_, Some(board::Put(x, y))) => goban.play(coords::from_api_colour(colour), x, y).is_ok(),
+        (_, Some(board::Pass)) | (_, None) => { goban.pass(coords::from_api_colour(colour)); true }
+    }
+}
+
+// the vertices of a handicap, None if one cannot be parsed
+fn parse_vertices(words: &[&str]) -> Option<Vec<(uint, uint)>> {
+    let mut points = Vec::new();
+    for word in words.iter() {
+        match coords::parse_vertex(*word) {
+            Some(p) => points.push(p),
+            None => return None
+        }
+    }
+    Some(points)
+}
+
+// replays the game of an SGF file accepted by the engine, rewound to the
+// given move as loadsgf does
+fn load(goban: &mut board::Board, file: &str, until: Option<uint>) -> Result<(), String> {
+    let mut loaded = goban.clone_without_history();
+    try!(sgf::load_on(&Path::new(file), &mut loaded));
+    match until {
+        Some(n) => while loaded.move_number() >= n && loaded.undo() {},
+        None => {}
+    }
+    *goban = loaded;
+    Ok(())
+}
+
+// updates our board from a command and the engine answer, returning a
+// description of the disagreement if there is one
+fn check(goban: &mut board::Board, command: &str, response: &str) -> Option<String> {
+    let mut words: Vec<&str> = command.words().collect();
+    if words.len() > 0 && from_str::<uint>(words[0]).is_some() {
+        words.remove(0);
+    }
+    let success = response.starts_with("=");
+    // answer without the "=id" prefix
+    let answers: Vec<&str> = response.words().skip(1).collect();
+    let answer = answers.as_slice().head().map(|&a| a).unwrap_or("");
+    match words.as_slice() {
+        ["boardsize", size] if success => {
+            from_str::<uint>(size).map(|s| goban.resize(s));
+            None
+        }
+        ["clear_board"] if success => { goban.clear(); None }
+        ["komi", komi] if success => {
+            from_str::<f32>(komi).map(|k| goban.set_komi(k));
+            None
+        }
+        ["fixed_handicap", stones] if success => match from_str::<uint>(stones) {
+            Some(n) => match (goban.place_fixed_handicap(n), parse_vertices(answers.as_slice())) {
+                (Some(ref ours), Some(ref theirs)) if ours == theirs => None,
+                _ => Some(format!("engine placed a different fixed handicap: {}", answers.connect(" ")))
+            },
+            None => None
+        },
+        // the engine chooses the points of a free handicap
+        ["place_free_handicap", _] if success => match parse_vertices(answers.as_slice()) {
+            Some(ref points) if goban.set_free_handicap(points.as_slice()) => None,
+            _ => Some(format!("engine placed an invalid free handicap: {}", answers.connect(" ")))
+        },
+        ["set_free_handicap", ..vertices] if success => match parse_vertices(vertices) {
+            Some(ref points) if goban.set_free_handicap(points.as_slice()) => None,
+            _ => Some(format!("engine accepted an invalid free handicap: {}", vertices.connect(" ")))
+        },
+        ["loadsgf", file] | ["loadsgf", file, _] if success => {
+            let until = words.as_slice().get(2).and_then(|&n| from_str::<uint>(n));
+            match load(goban, file, until) {
+                Err(e) => Some(format!("engine loaded a game we cannot: {}", e)),
+                Ok(()) => {
+                    let to_move = match goban.to_move() { board::Black => "black", board::White => "white" };
+                    if answer.to_ascii_lower().as_slice() == to_move {
+                        None
+                    } else {
+                        Some(format!("engine has {} to move after loadsgf, we have {}", answer, to_move))
+                    }
+                }
+            }
+        }
+        // the game is over after a resignation, until clear_board
+        ["play", ..] | ["genmove", ..] if goban.resigned().is_some() => None,
+        ["undo"] if success => {
+            if goban.undo() { None } else { Some(String::from_str("engine undid a move we do not have")) }
+        }
+        ["play", colour, vertex] => match (coords::parse_colour(colour), coords::parse_move(vertex)) {
+            (Some(c), Some(m)) => {
+                let legal = apply(goban, c, m);
+                match (success, legal) {
+                    (true, false) => Some(format!("engine accepted illegal move {} {}", colour, vertex)),
+                    (false, true) => {
+                        goban.undo();
+                        Some(format!("engine rejected legal move {} {}", colour, vertex))
+                    }
+                    _ => None
+                }
+            }
+            _ => None
+        },
+        ["genmove", colour] if success => match (coords::parse_colour(colour), coords::parse_move(answer)) {
+            (Some(c), Some(m)) => {
+                if apply(goban, c, m) { None } else { Some(format!("engine generated illegal move {}", answer)) }
+            }
+            _ => Some(format!("cannot parse generated move \"{}\"", answer))
+        },
+        _ => None
+    }
+}
@@ -1,30 +1,73 @@
-//! A bot playing randomly, but still following the rules.
+//! A bot playing randomly, but still following the rules. Tactical
+//! candidates (captures, atari escapes...) are tried before random moves.
 
-use std::rand::{task_rng, Rng};
+use std::rand::Rng;
 
 use board;
+use movegen;
+use status;
 
-pub fn genmove(goban: &mut board::Board, player: board::Colour) -> board::Vertex {
-    let size = goban.get_size();
-    let mut rng = task_rng();
-    let mut i = 0u;
-    // try at most 10 random moves
-    while i < 10 {
-        let (x, y) = (rng.gen_range(1u, size+1), rng.gen_range(1u, size+1));
-        if goban.play(player, x, y){
-            return board::Put(x,y);
+/// Playouts run to find the dead stones in cleanup mode.
+static cleanup_playouts : uint = 50;
+/// Fewest playouts run in cleanup mode, however short of time.
+static min_cleanup_playouts : uint = 10;
+
+/// Chooses the move of the player, without playing it.
+pub fn choose_move<R: Rng>(goban: &board::Board, player: board::Colour, rng: &mut R) -> board::Vertex {
+    // urgent moves first, but only a capture is worth a self-atari
+    for (priority, x, y) in movegen::candidates(goban, player, rng) {
+        if priority != movegen::Capture && goban.is_self_atari(player, x, y) {
+            continue;
+        }
+        if goban.check_move(player, x, y).is_ok() {
+            return board::Put(x, y);
         }
-        i = i+1;
     }
-    // if we reach this point, random failed, we go for a more deterministic
-    // approach
-    for x in range(1u, size+1) {
-        for y in range(1u, size+1) {
-            if goban.play(player, x, y){
-                return board::Put(x,y);
+    // then any legal move which does not fill one of our eyes, self-ataris
+    // only if nothing else is left
+    let moves: Vec<(uint, uint)> = goban.legal_moves(player).move_iter()
+                                        .filter(|&(x, y)| !goban.is_eye(player, x, y)).collect();
+    let (moves, self_ataris) = moves.partition(|&(x, y)| !goban.is_self_atari(player, x, y));
+    let moves = if moves.is_empty() { self_ataris } else { moves };
+    match rng.choose(moves.as_slice()) {
+        Some(&(x, y)) => board::Put(x, y),
+        // can play nothing ?
+        None => board::Pass
+    }
+}
+
+/// Move of the cleanup phase, once both players have passed: the
+/// liberties of the opponent groups estimated dead are filled until the
+/// groups are captured, the weakest first, then the dame are filled.
+/// Passes when neither is left. With a thinking time budget in seconds,
+/// fewer playouts are run when they would not fit in it. The move is not
+/// played.
+pub fn choose_cleanup_move<R: Rng>(goban: &board::Board, player: board::Colour, budget: Option<f64>,
+                                   rng: &mut R) -> board::Vertex {
+    let playouts = status::playouts_within(budget, cleanup_playouts, min_cleanup_playouts);
+    let statuses = status::estimate(goban, playouts, rng);
+    let mut targets: Vec<(uint, uint)> = statuses.iter().filter_map(|&(gid, st)| {
+        let grp = &goban.get_groups()[gid];
+        let (x, y) = grp.get_stones().next().unwrap();
+        if st == status::Dead && goban.at(x, y) != Some(player) {
+            Some((grp.liberty_count(), gid))
+        } else {
+            None
+        }
+    }).collect();
+    targets.sort();
+    for &(_, gid) in targets.iter() {
+        for (x, y) in goban.get_groups()[gid].get_liberties() {
+            if !goban.is_eye(player, x, y) && goban.check_move(player, x, y).is_ok() {
+                return board::Put(x, y);
             }
         }
     }
-    // can play nothing ?
+    // then the dame, worth a point under area rules
+    for &(x, y) in goban.dame().iter() {
+        if !goban.is_self_atari(player, x, y) && goban.check_move(player, x, y).is_ok() {
+            return board::Put(x, y);
+        }
+    }
     board::Pass
 }
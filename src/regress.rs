@@ -0,0 +1,147 @@
+//! Runner for GNU Go-style `.tst` regression files.
+//!
+//! A test file is a sequence of GTP commands. A numbered command may be
+//! followed by a `#? [expected]` line, the expected answer being a list
+//! of alternatives separated by `|`, optionally negated with a leading
+//! `!`. A trailing `*` marks a test that is currently expected to fail.
+//! Alternatives are compared to the answer literally, ignoring case.
+//!
+//! ```text
+//! boardsize 9
+//! play black E5
+//! 1 genmove white
+//! #? [!E5|pass]
+//! ```
+
+use std::ascii::StrAsciiExt;
+use std::io::File;
+
+use gtprust::api;
+use gtprust::api::GoBot;
+
+use coords::{parse_colour, parse_move, format_move};
+use gtp;
+
+/// Results of a regression run.
+pub struct Summary {
+    pub passed: uint,
+    pub failed: uint,
+    pub unexpected_passes: uint,
+    pub expected_failures: uint
+}
+
+/// Runs the given test file against a fresh bot, printing one line per
+/// surprising result on the output.
+pub fn run_file<W: Writer>(path: &Path, output: &mut W) -> Result<Summary, String> {
+    let contents = match File::open(path).read_to_string() {
+        Ok(c) => c,
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+    };
+    let mut bot = gtp::ClockGoBot::new();
+    let mut summary = Summary { passed: 0, failed: 0, unexpected_passes: 0, expected_failures: 0 };
+    // id and answer of the last numbered command
+    let mut last: Option<(uint, Result<String, String>)> = None;
+    for line in contents.as_slice().lines() {
+        let line = line.trim();
+        if line.starts_with("#?") {
+            let (id, answer) = match last.take() {
+                Some(l) => l,
+                None => return Err(format!("{}: expected answer without a numbered command", path.display()))
+            };
+            let (expected, should_fail) = parse_expected(line.slice_from(2).trim());
+            let answer_str = match answer { Ok(ref a) => a.clone(), Err(ref e) => format!("? {}", e) };
+            let matched = answer.is_ok() && expected.matches(answer_str.as_slice());
+            let msg = match (matched, should_fail) {
+                (true, false) => { summary.passed += 1; None }
+                (false, true) => { summary.expected_failures += 1; None }
+                (true, true) => {
+                    summary.unexpected_passes += 1;
+                    Some(format!("{} PASSED unexpectedly", id))
+                }
+                (false, false) => {
+                    summary.failed += 1;
+                    Some(format!("{} FAILED: got {}, expected {}", id, answer_str, line.slice_from(2).trim()))
+                }
+            };
+            match msg {
+                Some(m) => { let _ = writeln!(output, "{}:{}", path.display(), m); }
+                None => {}
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with("#") {
+            continue;
+        }
+        let mut words: Vec<&str> = line.words().collect();
+        let id = from_str::<uint>(words[0]);
+        if id.is_some() {
+            words.remove(0);
+        }
+        if words.is_empty() {
+            continue;
+        }
+        let answer = execute(&mut bot, words[0], words.slice_from(1));
+        last = id.map(|n| (n, answer));
+    }
+    Ok(summary)
+}
+
+struct Expected {
+    negated: bool,
+    alternatives: Vec<String>
+}
+
+impl Expected {
+    fn matches(&self, answer: &str) -> bool {
+        let answer = answer.trim().to_ascii_upper();
+        let found = self.alternatives.iter().any(|alt| alt.as_slice() == answer.as_slice());
+        found != self.negated
+    }
+}
+
+// parses "[!A1|B2]*" into the alternatives and whether failure is expected
+fn parse_expected(spec: &str) -> (Expected, bool) {
+    let should_fail = spec.ends_with("*");
+    let spec = spec.trim_right_chars('*').trim_left_chars('[').trim_right_chars(']');
+    let negated = spec.starts_with("!");
+    let spec = spec.trim_left_chars('!');
+    let alternatives = spec.split('|').map(|a| a.trim().to_ascii_upper()).collect();
+    (Expected { negated: negated, alternatives: alternatives }, should_fail)
+}
+
+// dispatches a single GTP command to the bot
+fn execute(bot: &mut gtp::ClockGoBot, command: &str, args: &[&str]) -> Result<String, String> {
+    let syntax_error = Err(String::from_str("syntax error"));
+    match command {
+        "name" => Ok(bot.gtp_name()),
+        "version" => Ok(bot.gtp_version()),
+        "clear_board" => { bot.gtp_clear_board(); Ok(String::new()) }
+        "komi" => match args.get(0).and_then(|k| from_str::<f32>(*k)) {
+            Some(k) => { bot.gtp_komi(k); Ok(String::new()) }
+            None => syntax_error
+        },
+        "boardsize" => match args.get(0).and_then(|s| from_str::<uint>(*s)) {
+            Some(size) => bot.gtp_boardsize(size).map(|_| String::new())
+                             .map_err(|_| String::from_str("unacceptable size")),
+            None => syntax_error
+        },
+        "play" if args.len() == 2 => match (parse_colour(args[0]), parse_move(args[1])) {
+            (Some(col), Some(mv)) => bot.gtp_play(api::ColouredMove { player: col, move: mv })
+                                        .map(|_| String::new())
+                                        .map_err(|_| String::from_str("illegal move")),
+            _ => syntax_error
+        },
+        "genmove" => match args.get(0).and_then(|c| parse_colour(*c)) {
+            Some(col) => Ok(format_move(bot.gtp_genmove(col))),
+            None => syntax_error
+        },
+        "undo" => bot.gtp_undo().map(|_| String::new()).map_err(|_| String::from_str("cannot undo")),
+        _ if bot.gtp_known_custom_command(command) => {
+            match bot.gtp_custom_command(command, args.connect(" ").as_slice()) {
+                (true, answer) => Ok(answer),
+                (false, error) => Err(error)
+            }
+        }
+        _ => Err(String::from_str("unknown command"))
+    }
+}
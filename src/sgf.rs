@@ -0,0 +1,459 @@
+//! Reading and writing of games in the SGF format. Games can be loaded
+//! either as their main line, replayed on a board with setup stones
+//! (AB/AW) placed first so handicap games load as well, or with all their
+//! variations as a GameTree.
+//!
+//! ```text
+//! (;GM[1]SZ[9]KM[0.5]HA[2]AB[cc][gg];W[ee](;B[];W[])(;B[dd]C[tesuji]))
+//! ```
+
+use std::io::File;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use board;
+use gametree::GameTree;
+
+/// A node of the game: its properties, each with all its values.
+pub type Node = Vec<(String, Vec<String>)>;
+
+/// A sequence of nodes, followed by the variations branching from its last
+/// node.
+pub struct Sequence {
+    pub nodes: Vec<Node>,
+    pub variations: Vec<Sequence>
+}
+
+// reads a property value, the opening bracket being already consumed
+fn parse_value<'a>(chars: &mut Peekable<char, Chars<'a>>) -> Result<String, String> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some(']') => return Ok(value),
+            Some('\\') => match chars.next() {
+                Some(c) => value.push_char(c),
+                None => return Err(String::from_str("unterminated property value"))
+            },
+            Some(c) => value.push_char(c),
+            None => return Err(String::from_str("unterminated property value"))
+        }
+    }
+}
+
+// reads the properties of a node, up to the next node or variation
+fn parse_node<'a>(chars: &mut Peekable<char, Chars<'a>>) -> Result<Node, String> {
+    let mut node: Node = Vec::new();
+    let mut ident = String::new();
+    loop {
+        match chars.peek().map(|&c| c) {
+            Some(';') | Some('(') | Some(')') | None => return Ok(node),
+            Some('[') => {
+                chars.next();
+                let value = try!(parse_value(chars));
+                if !ident.is_empty() {
+                    node.push((ident.clone(), Vec::new()));
+                    ident.truncate(0);
+                }
+                match node.mut_last() {
+                    Some(prop) => prop.mut1().push(value),
+                    None => return Err(String::from_str("property value without identifier"))
+                }
+            }
+            Some(c) => {
+                chars.next();
+                // lower case letters of long identifiers are ignored
+                if c.is_uppercase() {
+                    ident.push_char(c);
+                }
+            }
+        }
+    }
+}
+
+// reads a game tree, the opening parenthesis being already consumed
+fn parse_sequence<'a>(chars: &mut Peekable<char, Chars<'a>>) -> Result<Sequence, String> {
+    let mut seq = Sequence { nodes: Vec::new(), variations: Vec::new() };
+    loop {
+        match chars.next() {
+            Some(';') if seq.variations.is_empty() => seq.nodes.push(try!(parse_node(chars))),
+            Some('(') => seq.variations.push(try!(parse_sequence(chars))),
+            Some(')') if !seq.nodes.is_empty() => return Ok(seq),
+            Some(c) if c.is_whitespace() => {}
+            Some(c) => return Err(format!("unexpected '{}' in game tree", c)),
+            None => return Err(String::from_str("unterminated game tree"))
+        }
+    }
+}
+
+/// Parses the first game of an SGF file with all its variations.
+pub fn parse_tree(text: &str) -> Result<Sequence, String> {
+    let mut chars = text.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('(') => return parse_sequence(&mut chars),
+            Some(_) => {}
+            None => return Err(String::from_str("no game found"))
+        }
+    }
+}
+
+/// Parses the main line of the first game of an SGF file, which goes
+/// through the first variation of every node.
+pub fn parse(text: &str) -> Result<Vec<Node>, String> {
+    let mut seq = try!(parse_tree(text));
+    let mut nodes = Vec::new();
+    loop {
+        let Sequence { nodes: n, variations: v } = seq;
+        nodes.extend(n.move_iter());
+        match v.move_iter().next() {
+            Some(first) => seq = first,
+            None => return Ok(nodes)
+        }
+    }
+}
+
+// SGF points are two letters from 'a', for the column and for the row
+// counted from the top
+fn parse_point(value: &str, size: uint) -> Option<(uint, uint)> {
+    let bytes = value.as_bytes();
+    if bytes.len() != 2 || bytes[0] < 'a' as u8 || bytes[1] < 'a' as u8 {
+        return None;
+    }
+    let (x, row) = ((bytes[0] - 'a' as u8) as uint + 1, (bytes[1] - 'a' as u8) as uint);
+    if x > size || row >= size {
+        None
+    } else {
+        Some((x, size - row))
+    }
+}
+
+// a point or a compressed rectangle of points such as "aa:cc"
+fn parse_points(value: &str, size: uint) -> Option<Vec<(uint, uint)>> {
+    let corners: Vec<&str> = value.split(':').collect();
+    match corners.as_slice() {
+        [point] => parse_point(point, size).map(|p| vec!(p)),
+        [from, to] => match (parse_point(from, size), parse_point(to, size)) {
+            (Some((x1, y1)), Some((x2, y2))) if x1 <= x2 && y1 >= y2 => {
+                let mut points = Vec::new();
+                for x in range(x1, x2 + 1) {
+                    for y in range(y2, y1 + 1) {
+                        points.push((x, y));
+                    }
+                }
+                Some(points)
+            }
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn parse_move(value: &str, size: uint) -> Option<board::Vertex> {
+    // "tt" is an old notation for a pass on small boards
+    if value.is_empty() || (value == "tt" && size <= 19) {
+        Some(board::Pass)
+    } else {
+        parse_point(value, size).map(|(x, y)| board::Put(x, y))
+    }
+}
+
+fn parse_colour(value: &str) -> Option<board::Colour> {
+    match value {
+        "B" => Some(board::Black),
+        "W" => Some(board::White),
+        _ => None
+    }
+}
+
+/// Reads the main line as a game record, without checking the legality
+/// of the moves. Setup stones (AB/AW/AE) are only supported in the first
+/// node. The player to move first is given by PL, or is White after a
+/// handicap (HA) or setup stones unless the first move says otherwise.
+/// Without KM, the komi is the given one.
+pub fn to_record(nodes: &[Node], komi: f32) -> Result<board::GameRecord, String> {
+    // the points depend on the size, which comes first whatever the order
+    // of the properties of the root node; 19 is the SGF default
+    let size = match nodes.head().and_then(|root| root.iter().find(|&&(ref ident, _)| ident.as_slice() == "SZ")) {
+        Some(&(_, ref values)) => match from_str::<uint>(values[0].as_slice()) {
+            Some(size) if size > 0 && size <= board::board_maxsize => size,
+            _ => return Err(format!("invalid board size \"{}\"", values[0]))
+        },
+        None => 19
+    };
+    let mut record = board::GameRecord::new(size, komi);
+    let mut handicap = false;
+    for (n, node) in nodes.iter().enumerate() {
+        for &(ref ident, ref values) in node.iter() {
+            let value = values[0].as_slice();
+            match ident.as_slice() {
+                "SZ" if n == 0 => {}
+                "SZ" => return Err(format!("invalid board size \"{}\"", value)),
+                "KM" => match from_str::<f32>(value) {
+                    Some(komi) => record.komi = komi,
+                    None => return Err(format!("invalid komi \"{}\"", value))
+                },
+                "HA" => match from_str::<uint>(value) {
+                    Some(stones) => handicap = stones > 0,
+                    None => return Err(format!("invalid handicap \"{}\"", value))
+                },
+                "PL" if n == 0 => match parse_colour(value) {
+                    Some(colour) => record.start_player = Some(colour),
+                    None => return Err(format!("invalid player \"{}\"", value))
+                },
+                "AB" | "AW" | "AE" if n > 0 => {
+                    return Err(String::from_str("setup stones are only supported in the first node"));
+                }
+                "AB" | "AW" | "AE" => {
+                    let colour = parse_colour(ident.as_slice().slice_from(1));
+                    for v in values.iter() {
+                        let points = match parse_points(v.as_slice(), size) {
+                            Some(p) => p,
+                            None => return Err(format!("invalid setup point \"{}\"", v))
+                        };
+                        for &(x, y) in points.iter() {
+                            // a point set up twice keeps the last property
+                            record.setup.retain(|&(_, a, b)| (a, b) != (x, y));
+                            match colour {
+                                Some(c) => record.setup.push((c, x, y)),
+                                None => {}
+                            }
+                        }
+                    }
+                }
+                "B" | "W" => {
+                    let colour = parse_colour(ident.as_slice()).unwrap();
+                    let vertex = match parse_move(value, size) {
+                        Some(v) => v,
+                        None => return Err(format!("move {}: invalid point \"{}\"", record.moves.len() + 1, value))
+                    };
+                    record.moves.push((colour, vertex));
+                }
+                _ => {}
+            }
+        }
+    }
+    if record.start_player.is_none() && (handicap || !record.setup.is_empty()) {
+        record.start_player = Some(match record.moves.as_slice().head() {
+            Some(&(colour, _)) => colour,
+            None => board::White
+        });
+    }
+    Ok(record)
+}
+
+/// Replays the main line on the given board, checking the legality of
+/// every move. The board is cleared first but keeps its rules, and its
+/// komi without KM. Setup stones become part of the starting position,
+/// see GameRecord::replay_on.
+pub fn replay_on(nodes: &[Node], goban: &mut board::Board) -> Result<(), String> {
+    let record = try!(to_record(nodes, goban.get_komi()));
+    record.replay_on(goban)
+}
+
+/// Replays the main line on a new board, see replay_on.
+pub fn replay(nodes: &[Node]) -> Result<board::Board, String> {
+    let mut goban = board::Board::new();
+    try!(replay_on(nodes, &mut goban));
+    Ok(goban)
+}
+
+/// Loads the main line of an SGF file on the given board, see replay_on.
+pub fn load_on(path: &Path, goban: &mut board::Board) -> Result<(), String> {
+    let contents = match File::open(path).read_to_string() {
+        Ok(c) => c,
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+    };
+    match parse(contents.as_slice()).and_then(|nodes| replay_on(nodes.as_slice(), goban)) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("{}: {}", path.display(), e))
+    }
+}
+
+/// Loads the main line of an SGF file on a new board.
+pub fn load(path: &Path) -> Result<board::Board, String> {
+    let mut goban = board::Board::new();
+    try!(load_on(path, &mut goban));
+    Ok(goban)
+}
+
+// plays the nodes of a sequence and its variations in the tree, then goes
+// back to where it started
+fn add_sequence(tree: &mut GameTree, nodes: &[Node], variations: &[Sequence]) -> Result<(), String> {
+    let mut depth = 0u;
+    for node in nodes.iter() {
+        let mut comment = None;
+        for &(ref ident, ref values) in node.iter() {
+            let value = values[0].as_slice();
+            match ident.as_slice() {
+                "B" | "W" => {
+                    let colour = parse_colour(ident.as_slice()).unwrap();
+                    let number = tree.board().move_number() + 1;
+                    let vertex = match parse_move(value, tree.board().get_size()) {
+                        Some(v) => v,
+                        None => return Err(format!("move {}: invalid point \"{}\"", number, value))
+                    };
+                    if !tree.play(colour, vertex) {
+                        return Err(format!("move {} ({} {}) is illegal", number, colour, vertex));
+                    }
+                    depth += 1;
+                }
+                "AB" | "AW" | "AE" => return Err(String::from_str("setup stones are only supported in the first node")),
+                "C" => comment = Some(value),
+                _ => {}
+            }
+        }
+        match comment {
+            Some(c) => tree.set_comment(c),
+            None => {}
+        }
+    }
+    for variation in variations.iter() {
+        try!(add_sequence(tree, variation.nodes.as_slice(), variation.variations.as_slice()));
+    }
+    for _ in range(0, depth) {
+        tree.back();
+    }
+    Ok(())
+}
+
+/// Builds the game tree of a parsed game. Its first node gives the
+/// starting position, setup stones are not supported in later nodes.
+pub fn to_game_tree(game: &Sequence) -> Result<GameTree, String> {
+    let goban = try!(replay(game.nodes.slice_to(1)));
+    let mut tree = GameTree::new(goban);
+    // the comment of the first node belongs to the root
+    match game.nodes[0].iter().find(|&&(ref ident, _)| ident.as_slice() == "C") {
+        Some(&(_, ref values)) => tree.set_comment(values[0].as_slice()),
+        None => {}
+    }
+    try!(add_sequence(&mut tree, game.nodes.slice_from(1), game.variations.as_slice()));
+    Ok(tree)
+}
+
+/// Loads an SGF file with all its variations.
+pub fn load_tree(path: &Path) -> Result<GameTree, String> {
+    let contents = match File::open(path).read_to_string() {
+        Ok(c) => c,
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e))
+    };
+    match parse_tree(contents.as_slice()).and_then(|game| to_game_tree(&game)) {
+        Ok(tree) => Ok(tree),
+        Err(e) => Err(format!("{}: {}", path.display(), e))
+    }
+}
+
+// inverse of parse_point
+fn format_point(x: uint, y: uint, size: uint) -> String {
+    let mut point = String::new();
+    point.push_char(('a' as u8 + (x - 1) as u8) as char);
+    point.push_char(('a' as u8 + (size - y) as u8) as char);
+    point
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c == ']' || c == '\\' {
+            escaped.push_char('\\');
+        }
+        escaped.push_char(c);
+    }
+    escaped
+}
+
+// writes the move and comment of the current node
+fn write_node(tree: &GameTree, out: &mut String) {
+    match tree.last_move() {
+        Some((colour, board::Put(x, y))) => {
+            out.push_str(format!(";{}[{}]", colour, format_point(x, y, tree.board().get_size())).as_slice());
+        }
+        Some((colour, board::Pass)) => out.push_str(format!(";{}[]", colour).as_slice()),
+        None => {}
+    }
+    if !tree.get_comment().is_empty() {
+        out.push_str(format!("C[{}]", escape(tree.get_comment())).as_slice());
+    }
+}
+
+// writes the nodes after the current one
+fn write_variations(tree: &mut GameTree, out: &mut String) {
+    // a single variation just continues the sequence
+    let count = tree.variations().len();
+    for i in range(0, count) {
+        if count > 1 {
+            out.push_char('(');
+        }
+        tree.forward(i);
+        write_node(tree, out);
+        write_variations(tree, out);
+        tree.back();
+        if count > 1 {
+            out.push_char(')');
+        }
+    }
+}
+
+/// Writes a game tree with all its variations. The stones of the starting
+/// position are written as setup stones. The tree is left at its root.
+pub fn write_tree(tree: &mut GameTree) -> String {
+    tree.to_root();
+    let mut out = String::new();
+    {
+        let goban = tree.board();
+        let size = goban.get_size();
+        out.push_str(format!("(;GM[1]FF[4]SZ[{}]KM[{}]", size, goban.get_komi()).as_slice());
+        let stones: Vec<(board::Colour, uint, uint)> = goban.iter().filter_map(|(x, y, stone)| {
+            stone.map(|colour| (colour, x, y))
+        }).collect();
+        write_setup(stones.as_slice(), size, &mut out);
+        if !stones.is_empty() || goban.to_move() == board::White {
+            out.push_str(format!("PL[{}]", goban.to_move()).as_slice());
+        }
+    }
+    write_node(tree, &mut out);
+    write_variations(tree, &mut out);
+    out.push_char(')');
+    out
+}
+
+// writes the AB and AW properties of the given stones
+fn write_setup(stones: &[(board::Colour, uint, uint)], size: uint, out: &mut String) {
+    for &colour in [board::Black, board::White].iter() {
+        let points: Vec<String> = stones.iter().filter(|&&(c, _, _)| c == colour)
+                                        .map(|&(_, x, y)| format!("[{}]", format_point(x, y, size)))
+                                        .collect();
+        if !points.is_empty() {
+            out.push_str(format!("A{}{}", colour, points.concat()).as_slice());
+        }
+    }
+}
+
+/// Writes the game played on the board: its starting position as setup
+/// stones, its moves, and its result (RE) once it is over, by
+/// resignation or after two passes.
+pub fn write_game(goban: &board::Board) -> String {
+    let record = goban.record();
+    let size = record.size;
+    let mut out = format!("(;GM[1]FF[4]SZ[{}]KM[{}]", size, record.komi);
+    if goban.resigned().is_some() || goban.both_passed() {
+        out.push_str(format!("RE[{}]", goban.final_result()).as_slice());
+    }
+    write_setup(record.setup.as_slice(), size, &mut out);
+    // a handicap is black setup stones with White to move first
+    let start_player = record.start_player.unwrap_or(board::Black);
+    if record.setup.len() >= 2 && start_player == board::White
+       && record.setup.iter().all(|&(colour, _, _)| colour == board::Black) {
+        out.push_str(format!("HA[{}]", record.setup.len()).as_slice());
+    }
+    if !record.setup.is_empty() || start_player == board::White {
+        out.push_str(format!("PL[{}]", start_player).as_slice());
+    }
+    for &(colour, move) in record.moves.iter() {
+        match move {
+            board::Put(x, y) => out.push_str(format!(";{}[{}]", colour, format_point(x, y, size)).as_slice()),
+            board::Pass => out.push_str(format!(";{}[]", colour).as_slice())
+        }
+    }
+    out.push_char(')');
+    out
+}
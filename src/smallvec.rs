@@ -0,0 +1,126 @@
+//! A vector keeping its first few elements inline, used on the move path
+//! where collections almost never hold more than a handful of items.
+
+use std::vec;
+
+static inline_capacity: uint = 4;
+
+/// A vector storing up to 4 elements without allocating, and spilling
+/// to the heap only beyond that.
+pub struct SmallVec<T> {
+    inline: [Option<T>, ..4],
+    len: uint,
+    spill: Vec<T>
+}
+
+impl<T> SmallVec<T> {
+    pub fn new() -> SmallVec<T> {
+        SmallVec {
+            inline: [None, None, None, None],
+            len: 0,
+            spill: Vec::new()
+        }
+    }
+
+    pub fn len(&self) -> uint {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, value: T) {
+        if self.len < inline_capacity {
+            self.inline[self.len] = Some(value);
+        } else {
+            self.spill.push(value);
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        if self.len < inline_capacity {
+            self.inline[self.len].take()
+        } else {
+            self.spill.pop()
+        }
+    }
+
+    pub fn get<'a>(&'a self, index: uint) -> &'a T {
+        assert!(index < self.len);
+        if index < inline_capacity {
+            self.inline[index].as_ref().unwrap()
+        } else {
+            &self.spill[index - inline_capacity]
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> Items<'a, T> {
+        Items { vec: self, pos: 0 }
+    }
+
+    pub fn move_iter(self) -> MoveItems<T> {
+        let SmallVec { inline, len, spill } = self;
+        MoveItems {
+            inline: inline,
+            pos: 0,
+            inline_len: if len < inline_capacity { len } else { inline_capacity },
+            spill: spill.move_iter()
+        }
+    }
+}
+
+impl<T> Index<uint, T> for SmallVec<T> {
+    fn index<'a>(&'a self, index: &uint) -> &'a T {
+        self.get(*index)
+    }
+}
+
+impl<T: Clone> Clone for SmallVec<T> {
+    fn clone(&self) -> SmallVec<T> {
+        let mut result = SmallVec::new();
+        for item in self.iter() {
+            result.push(item.clone());
+        }
+        result
+    }
+}
+
+pub struct Items<'a, T> {
+    vec: &'a SmallVec<T>,
+    pos: uint
+}
+
+impl<'a, T> Iterator<&'a T> for Items<'a, T> {
+    fn next(&mut self) -> Option<&'a T> {
+        if self.pos < self.vec.len {
+            self.pos += 1;
+            Some(self.vec.get(self.pos - 1))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct MoveItems<T> {
+    inline: [Option<T>, ..4],
+    pos: uint,
+    inline_len: uint,
+    spill: vec::MoveItems<T>
+}
+
+impl<T> Iterator<T> for MoveItems<T> {
+    fn next(&mut self) -> Option<T> {
+        if self.pos < self.inline_len {
+            self.pos += 1;
+            self.inline[self.pos - 1].take()
+        } else {
+            self.spill.next()
+        }
+    }
+}
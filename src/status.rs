@@ -0,0 +1,253 @@
+//! Status of the groups at the end of the game, estimated by random
+//! playouts: a group whose points mostly end up owned by the opponent is
+//! dead. Playouts never fill eyes, so they end with every point owned by
+//! a single colour, or neutral. Nor do they fill the liberties of groups
+//! in seki, which would get the group of the player captured. The same
+//! playouts give the winning chances of the players. Captures and atari
+//! escapes are played first when there are some, as the move generator
+//! would.
+
+use std::cmp::{max, min};
+use std::rand::Rng;
+
+use board;
+use movegen;
+
+/// Estimated status of a group.
+#[deriving(PartialEq, Show)]
+pub enum Status {
+    Alive,
+    Dead,
+    /// Alive without territory, next to an opponent group also in seki.
+    Seki
+}
+
+/// Expected outcome of the game, see score_estimate.
+pub struct Estimate {
+    /// Mean area score of the playouts, komi included, positive when
+    /// Black is ahead.
+    pub score: f32,
+    /// How much each point belongs to Black, from -1 (always White's) to
+    /// 1 (always Black's), indexed by (x-1) * size + (y-1).
+    pub ownership: Vec<f32>
+}
+
+/// Share of the playouts a group must survive in to be alive.
+static alive_threshold : f32 = 0.5;
+/// Rough number of playouts run per second, to fit them in a budget.
+static playouts_per_second : f64 = 500.0;
+
+/// Number of playouts to run within a thinking time budget in seconds:
+/// as many as fit, but no more than most and no fewer than fewest. Most
+/// without a budget.
+pub fn playouts_within(budget: Option<f64>, most: uint, fewest: uint) -> uint {
+    match budget {
+        Some(seconds) => max(min((seconds * playouts_per_second) as uint, most), fewest),
+        None => most
+    }
+}
+
+// eye status of the points for Black and White, computed when first
+// asked and forgotten around the points which changed
+struct EyeCache {
+    size: uint,
+    eyes: Vec<Option<(bool, bool)>>
+}
+
+impl EyeCache {
+    fn new(size: uint) -> EyeCache {
+        EyeCache { size: size, eyes: Vec::from_elem(size * size, None) }
+    }
+
+    fn is_eye(&mut self, goban: &board::Board, colour: board::Colour, x: uint, y: uint) -> bool {
+        let i = (x-1) * self.size + y-1;
+        let (black, white) = match self.eyes[i] {
+            Some(eyes) => eyes,
+            None => {
+                let eyes = (goban.is_eye(board::Black, x, y), goban.is_eye(board::White, x, y));
+                *self.eyes.get_mut(i) = Some(eyes);
+                eyes
+            }
+        };
+        match colour { board::Black => black, board::White => white }
+    }
+
+    // the point changed: the eye status depends on the 3x3 square around
+    // a point, so its neighbours and diagonals are forgotten with it
+    fn changed(&mut self, x: uint, y: uint) {
+        for a in range(max(x, 2) - 1, min(x + 1, self.size) + 1) {
+            for b in range(max(y, 2) - 1, min(y + 1, self.size) + 1) {
+                *self.eyes.get_mut((a-1) * self.size + b-1) = None;
+            }
+        }
+    }
+}
+
+// liberties of the groups in seki: filling one of them gets the group of
+// the player captured
+fn seki_liberties(goban: &board::Board) -> Vec<(uint, uint)> {
+    let mut points = Vec::new();
+    for &gid in goban.seki_groups().iter() {
+        for point in goban.get_groups()[gid].get_liberties() {
+            if !points.contains(&point) {
+                points.push(point);
+            }
+        }
+    }
+    points
+}
+
+// plays random moves, which neither fill an eye of the player, nor a
+// liberty of a seki, nor are illegal, until both players pass; urgent
+// candidates come first, but only a capture is worth a self-atari
+fn playout<R: Rng>(goban: &mut board::Board, rng: &mut R) {
+    let max_moves = 3 * goban.get_size() * goban.get_size();
+    let mut player = goban.to_move();
+    let mut passes = 0u;
+    let mut seki = seki_liberties(goban);
+    let mut eyes = EyeCache::new(goban.get_size());
+    for _ in range(0, max_moves) {
+        let urgent = movegen::urgent_candidates(goban, player, rng).find(|&(priority, x, y)| {
+            !seki.contains(&(x, y)) && !eyes.is_eye(goban, player, x, y)
+                && goban.check_move(player, x, y).is_ok()
+                && (priority == movegen::Capture || !goban.is_self_atari(player, x, y))
+        }).map(|(_, x, y)| (x, y));
+        let chosen = match urgent {
+            Some(point) => Some(point),
+            None => {
+                let moves: Vec<(uint, uint)> = goban.legal_moves(player).move_iter()
+                                                    .filter(|&(x, y)| !eyes.is_eye(goban, player, x, y))
+                                                    .filter(|p| !seki.contains(p)).collect();
+                rng.choose(moves.as_slice()).map(|&p| p)
+            }
+        };
+        match chosen {
+            Some((x, y)) => {
+                let deads = goban.get_deads();
+                let _ = goban.play(player, x, y);
+                eyes.changed(x, y);
+                // captures can make or break a seki
+                if goban.get_deads() != deads {
+                    seki = seki_liberties(goban);
+                    // the stones taken off, by a capture or a suicide
+                    for mv in goban.get_history().back().iter() {
+                        for grp in mv.removed.iter() {
+                            for (a, b) in grp.get_stones() {
+                                eyes.changed(a, b);
+                            }
+                        }
+                    }
+                }
+                passes = 0;
+            }
+            None => {
+                goban.pass(player);
+                passes += 1;
+                if passes == 2 {
+                    break;
+                }
+            }
+        }
+        player = match player { board::Black => board::White, board::White => board::Black };
+    }
+}
+
+/// Runs the given number of playouts from the position and returns the
+/// status of every group, as (gid, status) with the keys of
+/// Board::get_groups. Groups found in seki by Board::seki_groups are
+/// reported as such when they survive.
+pub fn estimate<R: Rng>(goban: &board::Board, playouts: uint, rng: &mut R) -> Vec<(uint, Status)> {
+    let size = goban.get_size();
+    // number of playouts each point ended up owned by Black, and by White
+    let mut black = Vec::from_elem(size * size, 0u);
+    let mut white = Vec::from_elem(size * size, 0u);
+    for _ in range(0, playouts) {
+        let mut copy = goban.clone_without_history();
+        playout(&mut copy, rng);
+        let ownership = copy.territory_map();
+        for x in range(1u, size+1) {
+            for y in range(1u, size+1) {
+                match ownership[x-1][y-1] {
+                    Some(board::Black) => *black.get_mut((x-1) * size + y-1) += 1,
+                    Some(board::White) => *white.get_mut((x-1) * size + y-1) += 1,
+                    None => {}
+                }
+            }
+        }
+    }
+    let seki = goban.seki_groups();
+    goban.get_groups().iter().map(|(gid, grp)| {
+        let (x, y) = grp.get_stones().next().unwrap();
+        let owned = if goban.at(x, y) == Some(board::Black) { &black } else { &white };
+        let kept = grp.get_stones().map(|(x, y)| owned[(x-1) * size + y-1]).fold(0u, |a, b| a + b);
+        let survival = kept as f32 / (grp.stone_count() * playouts) as f32;
+        let status = if playouts > 0 && survival < alive_threshold {
+            Dead
+        } else if seki.contains(&gid) {
+            Seki
+        } else {
+            Alive
+        };
+        (gid, status)
+    }).collect()
+}
+
+/// Stones of the groups estimated dead, as expected by
+/// Board::territory_score.
+pub fn dead_stones(goban: &board::Board, statuses: &[(uint, Status)]) -> Vec<(uint, uint)> {
+    let mut dead = Vec::new();
+    for &(gid, status) in statuses.iter() {
+        if status == Dead {
+            dead.extend(goban.get_groups()[gid].get_stones());
+        }
+    }
+    dead
+}
+
+/// Share of the given number of playouts from the position won by the
+/// player, according to the area score. 0.5 without playouts.
+pub fn winrate<R: Rng>(goban: &board::Board, player: board::Colour, playouts: uint, rng: &mut R) -> f32 {
+    if playouts == 0 {
+        return 0.5;
+    }
+    let mut wins = 0u;
+    for _ in range(0, playouts) {
+        let mut copy = goban.clone_without_history();
+        playout(&mut copy, rng);
+        let score = copy.score();
+        if (player == board::Black && score > 0.0) || (player == board::White && score < 0.0) {
+            wins += 1;
+        }
+    }
+    wins as f32 / playouts as f32
+}
+
+/// Runs the given number of playouts from the position and averages their
+/// score and the owner of every point.
+pub fn score_estimate<R: Rng>(goban: &board::Board, playouts: uint, rng: &mut R) -> Estimate {
+    let size = goban.get_size();
+    let mut total = 0f32;
+    let mut ownership = Vec::from_elem(size * size, 0f32);
+    for _ in range(0, playouts) {
+        let mut copy = goban.clone_without_history();
+        playout(&mut copy, rng);
+        let scoring = copy.area_scoring();
+        total += scoring.score;
+        for x in range(1u, size+1) {
+            for y in range(1u, size+1) {
+                match scoring.ownership[x-1][y-1] {
+                    Some(board::Black) => *ownership.get_mut((x-1) * size + y-1) += 1.0,
+                    Some(board::White) => *ownership.get_mut((x-1) * size + y-1) -= 1.0,
+                    None => {}
+                }
+            }
+        }
+    }
+    if playouts > 0 {
+        total /= playouts as f32;
+        for o in ownership.mut_iter() {
+            *o /= playouts as f32;
+        }
+    }
+    Estimate { score: total, ownership: ownership }
+}